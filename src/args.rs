@@ -0,0 +1,25 @@
+// COMMAND LINE ARGUMENTS
+//
+// arguments of the form `--name <value>` or `--flag`, shared by the game and
+// the tools in src/bin/
+
+// value of a `--name <value>` command line argument
+pub fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let idx = args.iter().position(|arg| arg == name)?;
+    args.get(idx + 1).cloned()
+}
+
+pub fn has_arg(name: &str) -> bool {
+    std::env::args().any(|arg| arg == name)
+}
+
+// value of a `--name <value>` argument, default if it is not given
+pub fn parsed_arg<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match arg_value(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid value for {}: {}", name, value)),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,195 @@
+// LEVEL GENERATOR CLI
+//
+// generates a level without starting the game and writes its definition as
+// json, in the same format as resources/level1.json, so that it can be
+// tuned by hand and loaded like the other levels
+//
+// cargo run --bin levelgen -- [--seed N] [--generator NAME] [--width N]
+//     [--height N] [--rooms N] [--locks N] [--floors N] [--floor N]
+//     [--out FILE] [--preview] [--no-prefabs] [--biome-per-room] [--metrics]
+//     [--batch N] [--min-distance N] [--goal-in-dead-end]
+//     [--room-min-width N] [--room-max-width N] [--room-min-height N]
+//     [--room-max-height N] [--loop-ratio X] [--corridor-width N]
+//     [--prefab-chance X] [--decoration-density X] [--secret-chance X]
+//
+// --floor picks one floor (counting from 0) of a run that is --floors deep,
+// with the same seed and difficulty as in the game
+//
+// --preview prints the level as text instead, with `@` for the player, the
+// first letter of the name for enemies, the lock number for keys and `*` for
+// secret doors
+//
+// --min-distance and --goal-in-dead-end constrain the choice of the start
+// and goal rooms
+// --metrics prints the metrics of the level to stderr
+// --batch generates N levels with seeds derived from --seed and prints
+// aggregate metrics of all of them instead of a level
+//
+// the room sizes and the other settings of GeneratorParams default to the
+// ones of the game, they can be changed to compare batches of levels
+
+use std::error::Error;
+use std::fs;
+use std::process::ExitCode;
+
+use fastrand::Rng;
+
+use rust_thing2::args::{arg_value, has_arg, parsed_arg};
+use rust_thing2::game::error::ResourceError;
+use rust_thing2::game::level::TILE_SIZE;
+use rust_thing2::game::level_generator::dungeon::{floor_seed, generate_floor, DungeonParams};
+use rust_thing2::game::level_generator::metrics::{measure, MetricsSummary};
+use rust_thing2::game::level_generator::{generator_by_name, GeneratorParams, GENERATOR_NAMES};
+use rust_thing2::game::resources::{
+    parse_biome_definitions, parse_enemy_definitions, parse_json, parse_prefab_definitions,
+    parse_projectile_definitions, parse_tile_definitions, LevelDefinition, Resources, BIOMES_FILE,
+    ENEMIES_FILE, PREFABS_INDEX_FILE, PROJECTILES_FILE, SAMPLE_LEVEL_FILES, TILES_FILE,
+};
+
+fn read_file(file: &str) -> Result<String, ResourceError> {
+    fs::read_to_string(file).map_err(|err| ResourceError::Io {
+        file: file.to_string(),
+        message: err.to_string(),
+    })
+}
+
+// definitions only, textures are not needed to generate a level
+fn load_resources() -> Result<Resources, ResourceError> {
+    Ok(Resources::new(
+        parse_enemy_definitions(ENEMIES_FILE, &read_file(ENEMIES_FILE)?)?,
+        parse_projectile_definitions(PROJECTILES_FILE, &read_file(PROJECTILES_FILE)?)?,
+        parse_tile_definitions(TILES_FILE, &read_file(TILES_FILE)?)?,
+    ))
+}
+
+fn preview(def: &LevelDefinition) -> String {
+    let mut rows: Vec<Vec<char>> = def.tiles.iter().map(|row| row.chars().collect()).collect();
+    let mut mark = |x: f32, y: f32, ch: char| {
+        let (x, y) = ((x / TILE_SIZE) as usize, (y / TILE_SIZE) as usize);
+        if let Some(tile) = rows.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *tile = ch;
+        }
+    };
+    for enemy in &def.enemies {
+        mark(enemy.x, enemy.y, enemy.name.chars().next().unwrap_or('?'));
+    }
+    for key in &def.keys {
+        mark(
+            key.x,
+            key.y,
+            char::from_digit(key.lock as u32, 10).unwrap_or('?'),
+        );
+    }
+    for door in &def.secret_doors {
+        mark(
+            (door.x as f32 + 0.5) * TILE_SIZE,
+            (door.y as f32 + 0.5) * TILE_SIZE,
+            '*',
+        );
+    }
+    mark(def.player.x, def.player.y, '@');
+    rows.into_iter()
+        .map(|row| row.into_iter().collect::<String>() + "\n")
+        .collect()
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let seed = parsed_arg("--seed", fastrand::u64(..))?;
+    let name = arg_value("--generator").unwrap_or("random".to_string());
+    let generator = generator_by_name(&name).ok_or_else(|| {
+        format!(
+            "unknown generator {}, valid generators are: {}",
+            name,
+            GENERATOR_NAMES.join(", ")
+        )
+    })?;
+    let mut params = GeneratorParams::new(
+        parsed_arg("--width", 40)?,
+        parsed_arg("--height", 150)?,
+        parsed_arg("--rooms", 20)?,
+    );
+    params.room_min_width = parsed_arg("--room-min-width", params.room_min_width)?;
+    params.room_max_width = parsed_arg("--room-max-width", params.room_max_width)?;
+    params.room_min_height = parsed_arg("--room-min-height", params.room_min_height)?;
+    params.room_max_height = parsed_arg("--room-max-height", params.room_max_height)?;
+    params.loop_ratio = parsed_arg("--loop-ratio", params.loop_ratio)?;
+    params.corridor_width = parsed_arg("--corridor-width", params.corridor_width)?;
+    params.prefab_chance = parsed_arg("--prefab-chance", params.prefab_chance)?;
+    params.decoration_density = parsed_arg("--decoration-density", params.decoration_density)?;
+    params.secret_chance = parsed_arg("--secret-chance", params.secret_chance)?;
+
+    let res = load_resources()?;
+    params.tiles = res.tile_defintions.clone();
+    if !has_arg("--no-prefabs") {
+        params.prefabs =
+            parse_prefab_definitions(&read_file(PREFABS_INDEX_FILE)?, read_file, &res)?;
+    }
+    params.biomes = parse_biome_definitions(BIOMES_FILE, &read_file(BIOMES_FILE)?, &res)?;
+    params.biome_per_room = has_arg("--biome-per-room");
+    for file in SAMPLE_LEVEL_FILES {
+        let def: LevelDefinition = parse_json(file, &read_file(file)?)?;
+        params.samples.push(def.tiles);
+    }
+    let mut dungeon = DungeonParams::new(parsed_arg("--floors", 1)?, parsed_arg("--locks", 2)?);
+    dungeon.endpoints.min_distance = parsed_arg("--min-distance", 0)?;
+    dungeon.endpoints.goal_in_dead_end = has_arg("--goal-in-dead-end");
+    let floor = parsed_arg("--floor", 0)?;
+    // otherwise a batch would only count failures
+    params.validate()?;
+
+    if let Some(levels) = arg_value("--batch") {
+        let levels: usize = levels
+            .parse()
+            .map_err(|_| format!("invalid value for --batch: {}", levels))?;
+        let mut seeds = Rng::with_seed(seed);
+        let mut summary = MetricsSummary::default();
+        for _ in 0..levels {
+            let seed = floor_seed(seeds.u64(..), floor);
+            match generate_floor(generator.as_ref(), &params, &dungeon, floor, seed, &res) {
+                Ok((lvl, _, _, _)) => summary.add(&measure(&lvl)),
+                Err(_) => summary.add_failure(),
+            }
+        }
+        print!("{}", summary);
+        return Ok(());
+    }
+
+    let (lvl, def, seed, failed) = generate_floor(
+        generator.as_ref(),
+        &params,
+        &dungeon,
+        floor,
+        floor_seed(seed, floor),
+        &res,
+    )?;
+    // stderr, stdout may be used for the level itself
+    for (seed, err) in failed {
+        eprintln!("generating with seed {} failed: {}", seed, err);
+    }
+    eprintln!("generated level with seed {}", seed);
+    if has_arg("--metrics") {
+        eprint!("{}", measure(&lvl));
+    }
+
+    let output = if has_arg("--preview") {
+        preview(&def)
+    } else {
+        serde_json::to_string_pretty(&def)? + "\n"
+    };
+    match arg_value("--out") {
+        Some(file) => fs::write(&file, output)
+            .map_err(|err| format!("{}: could not be written: {}", file, err))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use macroquad::prelude::*;
 
+#[derive(Clone)]
 pub struct GameCamera {
     pub pos: Vec2,
     pub dim: Vec2,
@@ -7,7 +8,7 @@ pub struct GameCamera {
 
 impl GameCamera {}
 
-impl<'a> super::Game<'a> {
+impl super::Game {
     pub(super) fn update_camera(&mut self) {
         let cam = &mut self.camera;
 
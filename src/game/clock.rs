@@ -0,0 +1,64 @@
+// game time, used by everything that needs to know how much time passed in
+// the game (cooldowns, aggro, stats)
+// only advances while the game is stepped, so pausing the game, a debugger
+// stop or a loading hitch does not let timers expire
+#[derive(Clone)]
+pub struct GameClock {
+    // seconds
+    time: f64,
+    // factor applied to the frame time before it is turned into ticks,
+    // < 1 is slow motion, > 1 fast forward
+    scale: f32,
+    paused: bool,
+}
+
+pub const MIN_TIME_SCALE: f32 = 0.25;
+pub const MAX_TIME_SCALE: f32 = 4.;
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            time: 0.,
+            scale: 1.,
+            paused: false,
+        }
+    }
+}
+
+impl GameClock {
+    pub fn now(&self) -> f64 {
+        self.time
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt as f64;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    // how much game time passes for the given real frame time
+    pub fn scaled(&self, frame_time: f32) -> f32 {
+        if self.paused {
+            return 0.;
+        }
+        frame_time * self.scale
+    }
+}
@@ -1,5 +1,8 @@
 use macroquad::prelude::*;
 
+// snapshot of the player input for a single tick
+// mouse_pos is in world coordinates
+#[derive(Clone, Copy, Default)]
 pub struct Controls {
     pub is_q: bool,
 
@@ -9,14 +12,35 @@ pub struct Controls {
     pub is_right_mouse_down: bool,
 }
 
-impl<'a> super::Game<'a> {
-    pub(super) fn update_controls(&mut self) {
+impl Controls {
+    // reads the current input state from macroquad
+    // game_off is the offset of the game world on the screen
+    pub fn from_input(game_off: Vec2) -> Self {
         let mouse_pos = mouse_position();
-        let game_off = self.offset();
-        self.controls.mouse_pos = vec2(mouse_pos.0 - game_off.x, mouse_pos.1 - game_off.y);
-        self.controls.is_left_mouse_click = is_mouse_button_pressed(MouseButton::Left);
-        self.controls.is_right_mouse_click = is_mouse_button_pressed(MouseButton::Right);
-        self.controls.is_right_mouse_down = is_mouse_button_down(MouseButton::Right);
-        self.controls.is_q = is_key_down(KeyCode::Q);
+        Self {
+            mouse_pos: vec2(mouse_pos.0 - game_off.x, mouse_pos.1 - game_off.y),
+            is_left_mouse_click: is_mouse_button_pressed(MouseButton::Left),
+            is_right_mouse_click: is_mouse_button_pressed(MouseButton::Right),
+            is_right_mouse_down: is_mouse_button_down(MouseButton::Right),
+            is_q: is_key_down(KeyCode::Q),
+        }
+    }
+
+    // adds the clicks of other, which were not handled yet, to the clicks of
+    // self
+    pub fn with_clicks_from(self, other: &Controls) -> Self {
+        Self {
+            is_left_mouse_click: self.is_left_mouse_click || other.is_left_mouse_click,
+            is_right_mouse_click: self.is_right_mouse_click || other.is_right_mouse_click,
+            ..self
+        }
+    }
+
+    pub fn without_clicks(self) -> Self {
+        Self {
+            is_left_mouse_click: false,
+            is_right_mouse_click: false,
+            ..self
+        }
     }
 }
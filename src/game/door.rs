@@ -0,0 +1,108 @@
+// DOORS AND KEYS
+//
+// closed doors are solid until the player walks into them
+// locked doors only open for a player carrying the key of their lock
+// keys are picked up by walking over them and are never used up, one key
+// opens all doors of its lock
+// secret doors look like the wall they are in and open like closed doors
+
+use macroquad::math::{vec2, Vec2};
+
+use super::{
+    entity::{collides, Entity},
+    events::GameEvent,
+    level::{Level, Tile, TILE_SIZE},
+    resources::{LevelKeyDefinition, Resources, TilePosition},
+};
+
+pub const DOOR: char = 'g';
+pub const OPEN_DOOR: char = 'k';
+
+// chars of the locked doors, the index is the lock
+pub const LOCKED_DOORS: [char; 3] = ['h', 'i', 'j'];
+
+// key textures, the index is the lock
+pub const KEY_TEXTURES: [&str; 3] = ["key_red.png", "key_green.png", "key_blue.png"];
+
+// lock of a locked door char
+pub fn lock_of(ch: char) -> Option<usize> {
+    LOCKED_DOORS.iter().position(|&door| door == ch)
+}
+
+#[derive(Clone)]
+pub struct Key {
+    pub lock: usize,
+    pub pos: Vec2,
+}
+
+impl Entity for Key {
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    fn dim(&self) -> Vec2 {
+        vec2(TILE_SIZE, TILE_SIZE)
+    }
+}
+
+pub fn create_keys(defs: &[LevelKeyDefinition]) -> Vec<Key> {
+    defs.iter()
+        .map(|key| Key {
+            lock: key.lock,
+            pos: vec2(key.x, key.y),
+        })
+        .collect()
+}
+
+// tile indexes of the secret doors, positions outside of the level are
+// dropped
+pub fn secret_door_tiles(lvl: &Level, defs: &[TilePosition]) -> Vec<usize> {
+    defs.iter()
+        .filter(|pos| pos.x < lvl.width && pos.y < lvl.height)
+        .map(|pos| pos.y * lvl.width + pos.x)
+        .collect()
+}
+
+impl super::Game {
+    // opens the door at the position, if there is one that the player can
+    // open
+    pub(super) fn open_door_at(&mut self, pos: Vec2, res: &Resources) {
+        let Some(idx) = self.lvl.tile_index_at(pos) else {
+            return;
+        };
+        let ch = self.lvl.tiles[idx].ch;
+        let lock = lock_of(ch);
+        let secret = self.lvl.secret_doors.contains(&idx);
+        let can_open = match lock {
+            Some(lock) => self.player.keys.contains(&lock),
+            None => ch == DOOR || secret,
+        };
+        if !can_open {
+            return;
+        }
+        if secret {
+            self.lvl.secret_doors.retain(|&door| door != idx);
+        }
+        let fog = self.lvl.tiles[idx].fog.clone();
+        self.lvl.tiles[idx] = Tile {
+            fog,
+            ..Tile::from_char(OPEN_DOOR, &res.tile_defintions)
+        };
+        self.emit(GameEvent::DoorOpened { lock });
+    }
+
+    pub(super) fn pick_up_keys(&mut self) {
+        let mut i = 0;
+        while i < self.lvl.keys.len() {
+            if !collides(&self.player, &self.lvl.keys[i]) {
+                i += 1;
+                continue;
+            }
+            let key = self.lvl.keys.swap_remove(i);
+            if !self.player.keys.contains(&key.lock) {
+                self.player.keys.push(key.lock);
+            }
+            self.emit(GameEvent::KeyPickedUp { lock: key.lock });
+        }
+    }
+}
@@ -2,28 +2,55 @@ use macroquad::prelude::*;
 
 use super::{
     entity::{collides, is_out_of_lvl_bounds, Entity},
+    error::ResourceError,
+    events::{Actor, GameEvent},
     gfx::{AnimatedSprite, Frame},
     player::Ability,
     resources::Resources,
+    spatial::SpatialGrid,
 };
 
+#[derive(Clone)]
 pub enum EnemyStrategy {
     FollowPlayer,
     HorizontalPatrol,
     VerticalPatrol,
     NoMovement,
-    Projectile,
 }
 
-pub struct Enemy<'a> {
+impl EnemyStrategy {
+    // names of the strategies as used in enemies.json
+    pub const NAMES: [&'static str; 4] = [
+        "followPlayer",
+        "verticalPatrol",
+        "horizontalPatrol",
+        "noMovement",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "followPlayer" => Some(EnemyStrategy::FollowPlayer),
+            "verticalPatrol" => Some(EnemyStrategy::VerticalPatrol),
+            "horizontalPatrol" => Some(EnemyStrategy::HorizontalPatrol),
+            "noMovement" => Some(EnemyStrategy::NoMovement),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Enemy {
+    // unique per game, assigned by Game::add_enemy
+    pub id: usize,
     pub name: String,
     pub pos: Vec2,
     pub dim: Vec2,
-    pub sprite: AnimatedSprite<'a>,
+    pub sprite: AnimatedSprite,
     pub strategy: EnemyStrategy,
     pub hp: usize,
     pub hp_max: usize,
     pub dir: Vec2,
+    // pixels per second
     pub speed: f32,
     pub speed_solid: f32,
     pub fog_of_war: bool,
@@ -34,7 +61,7 @@ pub struct Enemy<'a> {
     pub aggro_duration: f64,
 }
 
-impl<'a> Entity for Enemy<'a> {
+impl Entity for Enemy {
     fn dim(&self) -> Vec2 {
         self.dim
     }
@@ -44,14 +71,11 @@ impl<'a> Entity for Enemy<'a> {
     }
 }
 
-fn collides_any(i: usize, enemies: &Vec<Enemy>) -> Option<usize> {
-    for j in 0..enemies.len() {
+fn collides_any(i: usize, enemies: &[Enemy], grid: &SpatialGrid) -> Option<usize> {
+    for j in grid.query(enemies[i].pos, enemies[i].dim) {
         if i == j {
             continue;
         }
-        if matches!(enemies[j].strategy, EnemyStrategy::Projectile) {
-            continue;
-        }
         if collides(&enemies[i], &enemies[j]) {
             return Some(j);
         }
@@ -59,17 +83,7 @@ fn collides_any(i: usize, enemies: &Vec<Enemy>) -> Option<usize> {
     None
 }
 
-fn count_non_projectiles(enemies: &Vec<Enemy>) -> usize {
-    let mut count = 0;
-    (0..enemies.len()).for_each(|i| {
-        if !matches!(enemies[i].strategy, EnemyStrategy::Projectile) {
-            count += 1;
-        }
-    });
-    count
-}
-
-pub fn count_nexus(enemies: &Vec<Enemy>) -> usize {
+pub fn count_nexus(enemies: &[Enemy]) -> usize {
     let mut count = 0;
     (0..enemies.len()).for_each(|i| {
         if enemies[i].name == "Nexus" {
@@ -83,17 +97,17 @@ fn is_dead(enemy: &Enemy) -> bool {
     enemy.hp == 0
 }
 
-impl<'a> super::Game<'a> {
-    pub(super) fn update_enemies(&mut self) {
+impl super::Game {
+    pub(super) fn update_enemies(&mut self, time: f64, dt: f32) {
         let enemies = &mut self.enemies;
         let player = &mut self.player;
         let lvl = &self.lvl;
+        let events = &mut self.events;
+        let grid = &mut self.enemy_grid;
         let mut to_remove = vec![];
-        let time = get_time();
-
-        let enemy_count = count_non_projectiles(enemies);
 
         (0..enemies.len()).for_each(|i| {
+            let old_pos = enemies[i].pos;
             let mut damage_player = false;
             let ignore_solid_checks =
                 lvl.is_solid_at(enemies[i].pos) || enemies[i].speed_solid > 0.;
@@ -107,40 +121,42 @@ impl<'a> super::Game<'a> {
                 EnemyStrategy::VerticalPatrol => {
                     enemies[i].dir.x = 0.;
                     if enemies[i].dir.y == 0. {
-                        enemies[i].dir.y = speed;
+                        enemies[i].dir.y = 1.;
                     }
-                    enemies[i].pos.y += enemies[i].dir.y;
+                    let step = enemies[i].dir.y * speed * dt;
+                    enemies[i].pos.y += step;
 
                     if collides(player, &enemies[i]) {
                         damage_player = true;
                     }
 
                     if (!ignore_solid_checks && lvl.is_solid_at(enemies[i].pos))
-                        || collides_any(i, enemies).is_some()
+                        || collides_any(i, enemies, grid).is_some()
                         || collides(player, &enemies[i])
                     {
                         // put back where they were, then reverse direction
-                        enemies[i].pos.y -= enemies[i].dir.y;
+                        enemies[i].pos.y -= step;
                         enemies[i].dir.y *= -1.;
                     }
                 }
                 EnemyStrategy::HorizontalPatrol => {
                     enemies[i].dir.y = 0.;
                     if enemies[i].dir.x == 0. {
-                        enemies[i].dir.x = speed;
+                        enemies[i].dir.x = 1.;
                     }
-                    enemies[i].pos.x += enemies[i].dir.x;
+                    let step = enemies[i].dir.x * speed * dt;
+                    enemies[i].pos.x += step;
 
                     if collides(player, &enemies[i]) {
                         damage_player = true;
                     }
 
                     if (!ignore_solid_checks && lvl.is_solid_at(enemies[i].pos))
-                        || collides_any(i, enemies).is_some()
+                        || collides_any(i, enemies, grid).is_some()
                         || collides(player, &enemies[i])
                     {
                         // put back where they were, then reverse direction
-                        enemies[i].pos.x -= enemies[i].dir.x;
+                        enemies[i].pos.x -= step;
                         enemies[i].dir.x *= -1.;
                     }
                 }
@@ -152,7 +168,7 @@ impl<'a> super::Game<'a> {
                     }
                     if enemies[i].aggro_cooldown.is_some() {
                         let dir = (player.pos - enemies[i].pos).normalize();
-                        enemies[i].pos.x += dir.x * speed;
+                        enemies[i].pos.x += dir.x * speed * dt;
 
                         if collides(player, &enemies[i]) {
                             damage_player = true;
@@ -160,14 +176,14 @@ impl<'a> super::Game<'a> {
 
                         // keep player on non-solid blocks
                         if (!ignore_solid_checks && lvl.is_solid_at(enemies[i].pos))
-                            || collides_any(i, enemies).is_some()
+                            || collides_any(i, enemies, grid).is_some()
                             || collides(player, &enemies[i])
                         {
                             // put player back where they were
-                            enemies[i].pos.x -= dir.x * speed;
+                            enemies[i].pos.x -= dir.x * speed * dt;
                         }
 
-                        enemies[i].pos.y += dir.y * speed;
+                        enemies[i].pos.y += dir.y * speed * dt;
 
                         if collides(player, &enemies[i]) {
                             damage_player = true;
@@ -175,32 +191,12 @@ impl<'a> super::Game<'a> {
 
                         // keep player on non-solid blocks
                         if (!ignore_solid_checks && lvl.is_solid_at(enemies[i].pos))
-                            || collides_any(i, enemies).is_some()
+                            || collides_any(i, enemies, grid).is_some()
                             || collides(player, &enemies[i])
                         {
                             // put player back where they were
-                            enemies[i].pos.y -= dir.y * speed;
-                        }
-                    }
-                }
-                EnemyStrategy::Projectile => {
-                    enemies[i].pos.y += enemies[i].dir.y * speed;
-                    enemies[i].pos.x += enemies[i].dir.x * speed;
-
-                    let collided = collides_any(i, enemies);
-                    if collided.is_some() {
-                        let enemy_idx = collided.unwrap();
-                        let mut damage = enemies[i].damage;
-                        // make nexus take 10* damage if its the last enemy
-                        if enemies[enemy_idx].name == "Nexus" && enemy_count == 1 {
-                            damage *= 10;
+                            enemies[i].pos.y -= dir.y * speed * dt;
                         }
-                        enemies[enemy_idx].hp -= std::cmp::min(enemies[enemy_idx].hp, damage);
-                        self.stats.damage_dealt += damage;
-                        if enemies[enemy_idx].hp == 0 {
-                            self.stats.enemies_killed += 1;
-                        }
-                        enemies[i].hp = 0;
                     }
                 }
                 EnemyStrategy::NoMovement => {
@@ -211,9 +207,18 @@ impl<'a> super::Game<'a> {
             if damage_player && !enemies[i].melee.is_on_cooldown(time) {
                 player.hp -= std::cmp::min(player.hp, enemies[i].damage);
                 enemies[i].melee.last_use = Some(time);
-                self.stats.damage_received += enemies[i].damage;
+                events.push(GameEvent::AbilityUsed {
+                    user: Actor::Enemy(enemies[i].id),
+                    name: enemies[i].melee.name.to_string(),
+                });
+                events.push(GameEvent::DamageReceived {
+                    source: Actor::Enemy(enemies[i].id),
+                    target: Actor::Player,
+                    amount: enemies[i].damage,
+                });
             }
-            enemies[i].sprite.update();
+            grid.update(i, old_pos, enemies[i].pos, enemies[i].dim);
+            enemies[i].sprite.update(dt);
         });
 
         (0..enemies.len()).for_each(|i| {
@@ -225,12 +230,24 @@ impl<'a> super::Game<'a> {
         (0..to_remove.len()).rev().for_each(|i| {
             enemies.swap_remove(to_remove[i]);
         });
+
+        // indexes changed, so the grid has to be rebuilt
+        if !to_remove.is_empty() {
+            grid.rebuild(enemies);
+        }
     }
 }
 
-pub fn create_enemy(name: String, pos: Vec2, res: &Resources) -> Enemy {
-    let def = res.enemy_definitions.get(&name).unwrap();
-    let tex = res.textures.get(&def.sprite.texture).unwrap();
+pub fn create_enemy(name: String, pos: Vec2, res: &Resources) -> Result<Enemy, ResourceError> {
+    let def = res
+        .enemy_definitions
+        .get(&name)
+        .ok_or_else(|| ResourceError::UnknownName {
+            kind: "enemy",
+            name: name.to_string(),
+            valid: res.enemy_names(),
+        })?;
+    let tex = res.texture_id(&def.sprite.texture).unwrap();
 
     let mut frames = vec![];
     for frame_def in &def.sprite.frames {
@@ -246,16 +263,15 @@ pub fn create_enemy(name: String, pos: Vec2, res: &Resources) -> Enemy {
         })
     }
 
-    let strategy = match def.strategy.as_str() {
-        "followPlayer" => EnemyStrategy::FollowPlayer,
-        "verticalPatrol" => EnemyStrategy::VerticalPatrol,
-        "horizontalPatrol" => EnemyStrategy::HorizontalPatrol,
-        "noMovement" => EnemyStrategy::NoMovement,
-        "projectile" => EnemyStrategy::Projectile,
-        _ => panic!("invalid enemy strategy"),
-    };
+    let strategy =
+        EnemyStrategy::from_name(&def.strategy).ok_or_else(|| ResourceError::UnknownName {
+            kind: "strategy",
+            name: def.strategy.to_string(),
+            valid: EnemyStrategy::NAMES.iter().map(|s| s.to_string()).collect(),
+        })?;
 
-    Enemy {
+    Ok(Enemy {
+        id: 0,
         name: def.name.to_string(),
         speed: def.speed,
         speed_solid: def.speed_solid,
@@ -281,5 +297,27 @@ pub fn create_enemy(name: String, pos: Vec2, res: &Resources) -> Enemy {
         aggro_radius: 160.,
         aggro_cooldown: None,
         aggro_duration: 5.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::resources::tests::resources;
+
+    #[test]
+    fn unknown_enemies_are_errors_without_a_location() {
+        let res = resources();
+        assert!(create_enemy("Spider".to_string(), Vec2::ZERO, &res).is_ok());
+        let err = create_enemy("Spyder".to_string(), Vec2::ZERO, &res)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "unknown enemy \"Spyder\", valid values are: {}",
+                res.enemy_names().join(", ")
+            )
+        );
     }
 }
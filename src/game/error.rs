@@ -0,0 +1,210 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ResourceError {
+    // file could not be read
+    Io {
+        file: String,
+        message: String,
+    },
+    // file is not valid json or does not match the expected structure
+    Parse {
+        file: String,
+        // json path of the offending value, eg. `[2].sprite.fps`
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // a value refers to something that does not exist (enemy name, strategy)
+    UnknownReference {
+        // file and json path where the reference was made
+        file: String,
+        path: String,
+        value: String,
+        valid: Vec<String>,
+    },
+    // a name that is not defined was used by the code or a generated level,
+    // so there is no file to point to
+    UnknownName {
+        // what the name refers to, eg. `enemy`
+        kind: &'static str,
+        name: String,
+        valid: Vec<String>,
+    },
+    // a definition that the game needs is missing from a file
+    MissingDefinition {
+        file: String,
+        name: String,
+    },
+    // a value is well formed, but does not make sense (eg. rows of different
+    // length)
+    Invalid {
+        file: String,
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::Io { file, message } => {
+                write!(f, "{}: could not be read: {}", file, message)
+            }
+            ResourceError::Parse {
+                file,
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: invalid value at `{}`: {}",
+                file, line, column, path, message
+            ),
+            ResourceError::UnknownReference {
+                file,
+                path,
+                value,
+                valid,
+            } => write!(
+                f,
+                "{}: at `{}`: unknown value \"{}\", valid values are: {}",
+                file,
+                path,
+                value,
+                valid.join(", ")
+            ),
+            ResourceError::UnknownName { kind, name, valid } => write!(
+                f,
+                "unknown {} \"{}\", valid values are: {}",
+                kind,
+                name,
+                valid.join(", ")
+            ),
+            ResourceError::MissingDefinition { file, name } => {
+                write!(f, "{}: missing definition for \"{}\"", file, name)
+            }
+            ResourceError::Invalid {
+                file,
+                path,
+                message,
+            } => write!(f, "{}: invalid value at `{}`: {}", file, path, message),
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerateError {
+    // a level needs at least one room
+    NoRooms,
+    // the level cannot hold even a single room
+    AreaTooSmall {
+        width: usize,
+        height: usize,
+        min_width: usize,
+        min_height: usize,
+    },
+    // more rooms were requested than can fit into the area
+    TooManyRooms {
+        rooms: usize,
+        max_rooms: usize,
+    },
+    // the rooms could not be placed within the allowed number of attempts
+    AttemptsExhausted {
+        placed: usize,
+        rooms: usize,
+        attempts: usize,
+    },
+    // the generator found less room for rooms than requested
+    NotEnoughSpace {
+        placed: usize,
+        rooms: usize,
+    },
+    // a room could not be connected to the rest of the level
+    Disconnected {
+        room: usize,
+    },
+    // wave function collapse found no connected level without contradiction
+    TooManyContradictions {
+        tries: usize,
+    },
+    // no pair of rooms satisfies the constraints for start and goal
+    NoStartAndGoal {
+        min_distance: usize,
+        goal_in_dead_end: bool,
+    },
+    // a parameter is out of its valid range
+    InvalidParam {
+        name: &'static str,
+        value: String,
+        expected: String,
+    },
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::NoRooms => write!(f, "a level needs at least one room"),
+            GenerateError::AreaTooSmall {
+                width,
+                height,
+                min_width,
+                min_height,
+            } => write!(
+                f,
+                "level area {}x{} is too small, it must be at least {}x{}",
+                width, height, min_width, min_height
+            ),
+            GenerateError::TooManyRooms { rooms, max_rooms } => write!(
+                f,
+                "{} rooms requested, but at most {} fit into the level",
+                rooms, max_rooms
+            ),
+            GenerateError::AttemptsExhausted {
+                placed,
+                rooms,
+                attempts,
+            } => write!(
+                f,
+                "only {} of {} rooms could be placed in {} attempts",
+                placed, rooms, attempts
+            ),
+            GenerateError::NotEnoughSpace { placed, rooms } => {
+                write!(f, "only {} of {} rooms could be placed", placed, rooms)
+            }
+            GenerateError::Disconnected { room } => {
+                write!(f, "room {} could not be connected", room)
+            }
+            GenerateError::TooManyContradictions { tries } => write!(
+                f,
+                "no connected level without contradictions was found in {} tries",
+                tries
+            ),
+            GenerateError::NoStartAndGoal {
+                min_distance,
+                goal_in_dead_end,
+            } => {
+                write!(
+                    f,
+                    "no start and goal room are at least {} tiles apart",
+                    min_distance
+                )?;
+                if *goal_in_dead_end {
+                    write!(f, " with the goal in a dead end")?;
+                }
+                Ok(())
+            }
+            GenerateError::InvalidParam {
+                name,
+                value,
+                expected,
+            } => write!(f, "invalid {} {}, expected {}", name, value, expected),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
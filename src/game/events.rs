@@ -0,0 +1,89 @@
+use super::{projectile::ProjectileOwner, GameStats};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Actor {
+    Player,
+    // id of the enemy
+    Enemy(usize),
+}
+
+impl From<ProjectileOwner> for Actor {
+    fn from(owner: ProjectileOwner) -> Self {
+        match owner {
+            ProjectileOwner::Player => Actor::Player,
+            ProjectileOwner::Enemy(id) => Actor::Enemy(id),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    // damage done by the player to an enemy
+    DamageDealt {
+        source: Actor,
+        target: Actor,
+        amount: usize,
+    },
+    // damage done to the player
+    DamageReceived {
+        source: Actor,
+        target: Actor,
+        amount: usize,
+    },
+    EnemyKilled {
+        id: usize,
+        name: String,
+    },
+    AbilityUsed {
+        user: Actor,
+        name: String,
+    },
+    ProjectileSpawned {
+        owner: ProjectileOwner,
+        name: String,
+    },
+    KeyPickedUp {
+        lock: usize,
+    },
+    // lock is None for doors that are not locked
+    DoorOpened {
+        lock: Option<usize>,
+    },
+    // the player stepped on stairs, the floor is changed by whoever owns the
+    // level generator (see Game::enter_floor and Game::enter_new_floor)
+    StairsTaken {
+        from: usize,
+        to: usize,
+    },
+    LevelWon,
+    LevelLost,
+}
+
+// anything that wants to react to what happens in the game (stats, hud,
+// audio, logging) implements this and gets fed the events drained from the
+// game after each update
+pub trait EventListener {
+    fn on_event(&mut self, event: &GameEvent);
+}
+
+impl EventListener for GameStats {
+    fn on_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::DamageDealt { amount, .. } => self.damage_dealt += amount,
+            GameEvent::DamageReceived { amount, .. } => self.damage_received += amount,
+            GameEvent::EnemyKilled { .. } => self.enemies_killed += 1,
+            _ => {}
+        }
+    }
+}
+
+impl super::Game {
+    pub fn emit(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    // returns all events emitted since the last call
+    pub fn drain_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
@@ -0,0 +1,205 @@
+// FLOORS
+//
+// a run can go over several floors connected by stairs
+// only the current floor lives in Game::lvl and Game::enemies, the others are
+// kept with their tiles (and fog), keys and surviving enemies, so that the
+// player can go back to them
+// the player with its hp is the same on all floors, but the keys it holds
+// belong to the floor they were found on, as lock ids repeat on every floor
+
+use std::mem;
+
+use macroquad::math::{vec2, Vec2};
+
+use super::{
+    door::{create_keys, secret_door_tiles},
+    enemy::{create_enemy, Enemy},
+    error::ResourceError,
+    events::GameEvent,
+    level::{Level, TILE_SIZE},
+    resources::{LevelDefinition, Resources},
+};
+
+pub const STAIRS_DOWN: char = 'l';
+pub const STAIRS_UP: char = 'm';
+
+#[derive(Clone)]
+pub struct Floor {
+    pub lvl: Level,
+    pub enemies: Vec<Enemy>,
+    // locks of the keys the player found on this floor
+    pub keys: Vec<usize>,
+}
+
+impl super::Game {
+    pub(super) fn take_stairs(&mut self) {
+        let ch = self
+            .lvl
+            .tile_index_at(self.player.pos)
+            .map(|idx| self.lvl.tiles[idx].ch);
+        let to = match ch {
+            Some(STAIRS_DOWN) => Some(self.floor + 1),
+            Some(STAIRS_UP) if self.floor > 0 => Some(self.floor - 1),
+            _ => None,
+        };
+        if let Some(to) = to.filter(|_| !self.on_stairs) {
+            self.emit(GameEvent::StairsTaken {
+                from: self.floor,
+                to,
+            });
+        }
+        self.on_stairs = to.is_some();
+    }
+
+    // whether stairs were taken since the last drain_events
+    pub fn floor_change_pending(&self) -> bool {
+        self.events
+            .iter()
+            .any(|event| matches!(event, GameEvent::StairsTaken { .. }))
+    }
+
+    // continues on a floor that was visited before, returns false if there
+    // is no such floor
+    pub fn enter_floor(&mut self, floor: usize) -> bool {
+        match self.other_floors.remove(&floor) {
+            Some(next) => {
+                self.switch_floor(floor, next, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // continues on a floor that is visited for the first time, the tiles of
+    // lvl_def are ignored in favor of lvl and the player position in favor
+    // of the stairs, if the floor has the ones that lead back
+    pub fn enter_new_floor(
+        &mut self,
+        floor: usize,
+        lvl: Level,
+        lvl_def: LevelDefinition,
+        res: &Resources,
+    ) -> Result<(), ResourceError> {
+        let mut enemies = vec![];
+        for enemy in lvl_def.enemies {
+            enemies.push(create_enemy(enemy.name, vec2(enemy.x, enemy.y), res)?);
+        }
+
+        let mut lvl = lvl;
+        lvl.keys = create_keys(&lvl_def.keys);
+        lvl.secret_doors = secret_door_tiles(&lvl, &lvl_def.secret_doors);
+        self.switch_floor(
+            floor,
+            Floor {
+                lvl,
+                enemies: vec![],
+                keys: vec![],
+            },
+            Some(vec2(lvl_def.player.x, lvl_def.player.y)),
+        );
+        for enemy in enemies {
+            self.add_enemy(enemy);
+        }
+        Ok(())
+    }
+
+    // fallback is where the player arrives if the floor has no stairs that
+    // lead back (single room floors only have stairs down), otherwise it
+    // stays where it was
+    fn switch_floor(&mut self, floor: usize, next: Floor, fallback: Option<Vec2>) {
+        let prev = Floor {
+            lvl: mem::replace(&mut self.lvl, next.lvl),
+            enemies: mem::replace(&mut self.enemies, next.enemies),
+            keys: mem::replace(&mut self.player.keys, next.keys),
+        };
+        self.other_floors.insert(self.floor, prev);
+
+        // the player arrives on the stairs that lead back
+        let arrival = if floor > self.floor {
+            STAIRS_UP
+        } else {
+            STAIRS_DOWN
+        };
+        if let Some(idx) = self.lvl.tiles.iter().position(|tile| tile.ch == arrival) {
+            self.player.pos = (self.lvl.pos_by_index(idx) + vec2(0.5, 0.5)) * TILE_SIZE;
+        } else if let Some(pos) = fallback {
+            self.player.pos = pos;
+        }
+        self.player.target_pos = None;
+        self.on_stairs = true;
+        self.floor = floor;
+
+        self.world.dim = vec2(
+            (self.lvl.width as f32) * TILE_SIZE,
+            (self.lvl.height as f32) * TILE_SIZE,
+        );
+        self.enemy_grid.rebuild(&self.enemies);
+        self.projectiles.clear();
+        self.update_camera();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        controls::Controls,
+        level::Tile,
+        resources::{
+            tests::{level_from_rows, resources},
+            Point,
+        },
+        tests::{game, nexus_at},
+        TICK,
+    };
+
+    // a single room with only stairs down, like generated single room floors
+    const ROWS: [&str; 5] = ["aaaaaaa", "accccca", "accccla", "accccca", "aaaaaaa"];
+
+    fn floor_def(player: Point) -> LevelDefinition {
+        LevelDefinition {
+            tiles: ROWS.iter().map(|row| row.to_string()).collect(),
+            player,
+            enemies: vec![nexus_at(112., 48.)],
+            keys: vec![],
+            secret_doors: vec![],
+        }
+    }
+
+    #[test]
+    fn the_player_starts_at_the_definition_without_stairs_back() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        game.player.pos = vec2(272., 176.);
+        let lvl = level_from_rows(&ROWS, vec![]);
+        game.enter_new_floor(1, lvl, floor_def(Point { x: 80., y: 48. }), &res)
+            .unwrap();
+        assert_eq!(game.floor, 1);
+        assert_eq!(game.player.pos, vec2(80., 48.));
+    }
+
+    #[test]
+    fn the_player_arrives_on_the_stairs_back() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        game.lvl.tiles[2 * game.lvl.width + 2] = Tile::from_char(STAIRS_DOWN, &res.tile_defintions);
+        let lvl = level_from_rows(&ROWS, vec![]);
+        game.enter_new_floor(1, lvl, floor_def(Point { x: 80., y: 48. }), &res)
+            .unwrap();
+        assert!(game.enter_floor(0));
+        assert_eq!(game.player.pos, vec2(80., 80.));
+    }
+
+    #[test]
+    fn ticks_stop_once_stairs_are_taken() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        game.floors = 2;
+        game.lvl.tiles[game.lvl.width + 1] = Tile::from_char(STAIRS_DOWN, &res.tile_defintions);
+        game.clock.set_scale(4.);
+        game.advance(0.25, Controls::default(), &res);
+        assert!(game.floor_change_pending());
+        assert!((game.clock.now() - TICK as f64).abs() < 1e-6);
+        assert_eq!(game.accumulator, 0.);
+    }
+}
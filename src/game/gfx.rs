@@ -1,23 +1,25 @@
-use macroquad::{
-    math::{Rect, Vec2},
-    texture::Texture2D,
-    time::get_frame_time,
-};
+use macroquad::math::{Rect, Vec2};
 
-pub struct StaticSprite<'a> {
-    pub frame: Frame<'a>,
+// handle of a texture loaded by Resources, resolved at draw time
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureId(pub usize);
+
+#[derive(Clone)]
+pub struct StaticSprite {
+    pub frame: Frame,
 }
 
-pub struct AnimatedSprite<'a> {
-    pub frames: Vec<Frame<'a>>,
+#[derive(Clone)]
+pub struct AnimatedSprite {
+    pub frames: Vec<Frame>,
     pub frame_index: usize,
     pub fps: u8,
     pub time: f32,
 }
 
-impl<'a> AnimatedSprite<'a> {
-    pub fn update(&mut self) {
-        self.time += get_frame_time();
+impl AnimatedSprite {
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
         if self.time > 1. / self.fps as f32 {
             self.frame_index += 1;
             self.time = 0.0;
@@ -26,8 +28,9 @@ impl<'a> AnimatedSprite<'a> {
     }
 }
 
-pub struct Frame<'a> {
-    pub texture: &'a Texture2D,
+#[derive(Clone)]
+pub struct Frame {
+    pub texture: TextureId,
     pub source_rect: Rect, // source rect in the texture
     pub dest_size: Vec2,   // drawing size
 }
@@ -1,5 +1,7 @@
+#[derive(Clone)]
 pub struct Item {}
 
+#[derive(Clone)]
 pub struct Inventory {
     pub head: Option<Item>,
     pub body: Option<Item>,
@@ -2,19 +2,27 @@ use std::collections::HashMap;
 
 use macroquad::prelude::*;
 
-use super::Game;
+use super::{
+    door::Key,
+    resources::{LevelEnemyDefinition, TileDefinition},
+    Game,
+};
 
+#[derive(Clone)]
 pub struct World {
     pub dim: Vec2,
 }
 
 pub const TILE_SIZE: f32 = 32.;
 
+#[derive(Clone)]
 pub enum FogLevel {
     Opaque,
     HalfTransparent,
     Transparent,
 }
+
+#[derive(Clone)]
 pub struct Tile {
     pub fog: FogLevel,
     pub solid: bool,
@@ -22,15 +30,34 @@ pub struct Tile {
 }
 
 impl Tile {
-    pub fn from_char(ch: char) -> Self {
+    // solidity comes from the tile definitions, characters without one are
+    // solid
+    pub fn from_char(ch: char, tiles: &HashMap<char, TileDefinition>) -> Self {
         Self {
             fog: FogLevel::Opaque,
-            solid: !matches!(ch, 'c' | 'd' | 'e' | 'f'),
+            solid: tiles.get(&ch).is_none_or(|def| def.solid),
             ch,
         }
     }
 }
 
+// what a room is for, assigned by the generator from the position of the
+// room in the room graph
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoomRole {
+    #[default]
+    Normal,
+    Start,
+    // guards the goal
+    Boss,
+    Treasure,
+    // safe room without enemies
+    Shop,
+    // behind a secret door
+    Secret,
+}
+
+#[derive(Clone)]
 pub struct Room {
     pub x: usize,
     pub y: usize,
@@ -40,9 +67,38 @@ pub struct Room {
     // indexes of other rooms where this room (directly or indirectly) connects
     // to
     pub connections: HashMap<usize, bool>,
+
+    // name of the prefab the room was built from
+    pub prefab: Option<String>,
+    // name of the biome the room was painted with
+    pub biome: Option<String>,
+    pub role: RoomRole,
+    // enemies that come with the room (from its prefab), positions in pixels
+    pub enemies: Vec<LevelEnemyDefinition>,
+    // tiles where a key can be placed (from its prefab)
+    pub key_spots: Vec<(usize, usize)>,
 }
 
 impl Room {
+    pub fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Self {
+            x,
+            y,
+            w,
+            h,
+            connections: HashMap::new(),
+            prefab: None,
+            biome: None,
+            role: RoomRole::Normal,
+            enemies: vec![],
+            key_spots: vec![],
+        }
+    }
+
+    pub fn is_border(&self, x: usize, y: usize) -> bool {
+        x == self.x || y == self.y || x == self.x + self.w - 1 || y == self.y + self.h - 1
+    }
+
     pub fn center(&self) -> Vec2 {
         vec2(
             self.x as f32 + self.w as f32 / 2.,
@@ -51,35 +107,56 @@ impl Room {
     }
 }
 
+#[derive(Clone)]
 pub struct Level {
     pub width: usize,
     pub height: usize,
     pub tiles: Vec<Tile>,
     pub rooms: Vec<Room>,
+    // rooms where the player starts and the goal (nexus or stairs down) is,
+    // chosen by the generator
+    pub start_room: Option<usize>,
+    pub goal_room: Option<usize>,
+    // keys lying around, not yet picked up
+    pub keys: Vec<Key>,
+    // indexes of wall tiles that open like doors
+    pub secret_doors: Vec<usize>,
 }
 
 impl Level {
     // Loads a level from a string
     // all lines in the string need to be of the same length for this to
     // work correctly right now
-    // unknown characters in the string will result in empty tiles
-    pub fn load_from_string(level_string: &str) -> Self {
-        let mut tiles: Vec<Tile> = Vec::new();
+    // unknown characters in the string will result in solid tiles
+    pub fn load_from_string(level_string: &str, tiles: &HashMap<char, TileDefinition>) -> Self {
+        let mut level_tiles: Vec<Tile> = Vec::new();
         let mut height = 0;
         for ch in level_string.chars() {
             match ch {
                 '\n' => height += 1,
-                _ => tiles.push(Tile::from_char(ch)),
+                _ => level_tiles.push(Tile::from_char(ch, tiles)),
             }
         }
         Level {
-            width: tiles.len() / height,
+            width: level_tiles.len() / height,
             height,
-            tiles,
+            tiles: level_tiles,
             rooms: vec![],
+            start_room: None,
+            goal_room: None,
+            keys: vec![],
+            secret_doors: vec![],
         }
     }
 
+    // one string per row of tiles, the format of LevelDefinition::tiles
+    pub fn to_rows(&self) -> Vec<String> {
+        self.tiles
+            .chunks(self.width)
+            .map(|row| row.iter().map(|tile| tile.ch).collect())
+            .collect()
+    }
+
     pub fn is_solid_at(&self, pos: Vec2) -> bool {
         let index = self.tile_index_at(pos);
         if let Some(index) = index {
@@ -129,7 +206,7 @@ impl Level {
     }
 
     pub fn tile_index_left(&self, tile_index: usize) -> Option<usize> {
-        if tile_index % self.width != 0 {
+        if !tile_index.is_multiple_of(self.width) {
             return Some(tile_index - 1);
         }
 
@@ -137,7 +214,7 @@ impl Level {
     }
 
     pub fn tile_index_right(&self, tile_index: usize) -> Option<usize> {
-        if (tile_index + 1) % self.width != 0 {
+        if !(tile_index + 1).is_multiple_of(self.width) {
             return Some(tile_index + 1);
         }
 
@@ -151,7 +228,7 @@ impl Level {
     }
 }
 
-impl<'a> Game<'a> {
+impl Game {
     pub(super) fn update_level(&mut self) {
         let lvl = &mut self.lvl;
 
@@ -0,0 +1,137 @@
+// BIOMES
+//
+// generators build levels from placeholder tiles (WALL, CORRIDOR_WALL and
+// GROUND), this pass paints them with the tiles of biomes from
+// resources/biomes.json
+// the level gets one biome, which is also used for everything outside of
+// rooms, and with biome_per_room every room gets a random one of its own
+// rooms remember their biome, so that only enemies living there are placed
+
+use fastrand::Rng;
+
+use super::{corridor::room_owners, GeneratorParams, CORRIDOR_WALL, GROUND, WALL};
+use crate::game::{
+    level::{Level, Tile},
+    resources::BiomeDefinition,
+};
+
+// the biome tile replacing a placeholder, None for tiles that stay as they
+// are (doors, decorations of prefabs, ..)
+fn paint(biome: &BiomeDefinition, ch: char, in_room: bool) -> Option<char> {
+    match (ch, in_room) {
+        (GROUND, true) => Some(biome.floor),
+        (GROUND, false) => Some(biome.corridor),
+        (WALL | CORRIDOR_WALL, true) => Some(biome.wall),
+        (WALL | CORRIDOR_WALL, false) => Some(biome.corridor_wall),
+        _ => None,
+    }
+}
+
+// paints the level with the biome of the given index, or its rooms with
+// random ones
+pub fn apply_biomes(level: &mut Level, params: &GeneratorParams, biome: usize, rng: &mut Rng) {
+    if params.biomes.is_empty() {
+        return;
+    }
+    let level_biome = &params.biomes[biome % params.biomes.len()];
+    let room_biomes: Vec<&BiomeDefinition> = (0..level.rooms.len())
+        .map(|_| match params.biome_per_room {
+            true => &params.biomes[rng.usize(0..params.biomes.len())],
+            false => level_biome,
+        })
+        .collect();
+
+    let owners = room_owners(level);
+    for (idx, owner) in owners.into_iter().enumerate() {
+        let biome = match owner {
+            Some(room) => room_biomes[room],
+            None => level_biome,
+        };
+        if let Some(ch) = paint(biome, level.tiles[idx].ch, owner.is_some()) {
+            level.tiles[idx] = Tile::from_char(ch, &params.tiles);
+        }
+    }
+    for (room, biome) in level.rooms.iter_mut().zip(room_biomes) {
+        room.biome = Some(biome.name.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room, level_generator::tests::params_with_tiles, resources::tests::level_from_rows,
+    };
+
+    // a room with a corridor leaving it to the east
+    const ROWS: [&str; 5] = ["aaaaabb", "acccccb", "acccabb", "acccabb", "aaaaabb"];
+
+    fn biome(name: &str, wall: char, corridor_wall: char, floor: char) -> BiomeDefinition {
+        BiomeDefinition {
+            name: name.to_string(),
+            wall,
+            corridor_wall,
+            floor,
+            corridor: GROUND,
+            decorations: vec![],
+            enemies: vec![],
+        }
+    }
+
+    fn params() -> GeneratorParams {
+        let mut params = params_with_tiles(7, 5, 1);
+        params.biomes = vec![
+            biome("Crypt", 'a', 'b', 'c'),
+            biome("Garden", 'b', 'a', 'd'),
+        ];
+        params
+    }
+
+    fn painted(params: &GeneratorParams, rooms: Vec<Room>, biome: usize) -> Level {
+        let mut level = level_from_rows(&ROWS, rooms);
+        apply_biomes(&mut level, params, biome, &mut Rng::with_seed(1));
+        level
+    }
+
+    #[test]
+    fn rooms_and_corridors_are_painted_differently() {
+        let level = painted(&params(), vec![Room::new(0, 0, 5, 5)], 1);
+        assert_eq!(
+            level.to_rows(),
+            vec!["bbbbbaa", "bddddca", "bdddbaa", "bdddbaa", "bbbbbaa"]
+        );
+        assert_eq!(level.rooms[0].biome.as_deref(), Some("Garden"));
+    }
+
+    #[test]
+    fn floors_go_round_the_biomes() {
+        let params = params();
+        let rooms = || vec![Room::new(0, 0, 5, 5)];
+        assert_eq!(
+            painted(&params, rooms(), 3).to_rows(),
+            painted(&params, rooms(), 1).to_rows()
+        );
+        let level = painted(&params, rooms(), 2);
+        assert_eq!(level.rooms[0].biome.as_deref(), Some("Crypt"));
+        // placeholders outside of rooms are corridor walls of the crypt
+        assert_eq!(level.to_rows()[0], "aaaaabb");
+    }
+
+    #[test]
+    fn rooms_can_get_biomes_of_their_own() {
+        let mut params = params();
+        params.biome_per_room = true;
+        let mut names = vec![];
+        for seed in 0..10 {
+            let mut level = level_from_rows(&ROWS, vec![Room::new(0, 0, 5, 5)]);
+            apply_biomes(&mut level, &params, 0, &mut Rng::with_seed(seed));
+            let name = level.rooms[0].biome.clone().unwrap();
+            let floor = params.biomes.iter().find(|b| b.name == name).unwrap().floor;
+            assert_eq!(level.to_rows()[2].chars().nth(2), Some(floor));
+            // outside of the room the biome of the level stays
+            assert_eq!(level.to_rows()[0].chars().nth(6), Some('b'));
+            names.push(name);
+        }
+        assert!(names.contains(&"Crypt".to_string()) && names.contains(&"Garden".to_string()));
+    }
+}
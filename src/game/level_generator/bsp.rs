@@ -0,0 +1,264 @@
+// BSP LEVEL GENERATOR
+//
+// splits the level area recursively into two parts until there is one part
+// (leaf) per requested room, then places one room in every leaf and connects
+// the rooms of sibling parts with each other, walking up the tree
+// loops are added afterwards, like in the random room generator
+//
+// +---------+-----+
+// |  room   |room |
+// |         +-----+
+// +----+----+room |
+// |room|room|     |
+// +----+----+-----+
+
+use fastrand::Rng;
+
+use super::{
+    add_loops, add_room, connect_rooms, empty_level,
+    prefab::{self, add_prefab, random_prefab},
+    GeneratorParams, LevelGenerator, CORRIDOR_WALL, GROUND,
+};
+use crate::game::{
+    error::GenerateError,
+    level::{Level, Room},
+};
+
+// a split is placed between 40% and 60% of the split side
+const SPLIT_MIN_RATIO: f32 = 0.4;
+const SPLIT_MAX_RATIO: f32 = 0.6;
+
+// rooms fill at least this much of their leaf in each direction
+const ROOM_MIN_FILL: f32 = 0.6;
+
+pub struct BspGenerator;
+
+struct BspNode {
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    children: Option<(usize, usize)>,
+    // index into Level::rooms of the room placed in this leaf
+    room: Option<usize>,
+}
+
+impl BspNode {
+    fn can_split_horizontally(&self, params: &GeneratorParams) -> bool {
+        self.w >= params.room_min_width * 2
+    }
+
+    fn can_split_vertically(&self, params: &GeneratorParams) -> bool {
+        self.h >= params.room_min_height * 2
+    }
+}
+
+fn split(nodes: &mut Vec<BspNode>, idx: usize, params: &GeneratorParams, rng: &mut Rng) {
+    let node = &nodes[idx];
+    let horizontally = if node.can_split_horizontally(params) && node.can_split_vertically(params) {
+        // prefer splitting the longer side to keep the parts square-ish
+        if node.w == node.h {
+            rng.bool()
+        } else {
+            node.w > node.h
+        }
+    } else {
+        node.can_split_horizontally(params)
+    };
+
+    let (size, min) = if horizontally {
+        (node.w, params.room_min_width)
+    } else {
+        (node.h, params.room_min_height)
+    };
+    let lo = ((size as f32 * SPLIT_MIN_RATIO) as usize).max(min);
+    let hi = ((size as f32 * SPLIT_MAX_RATIO) as usize).min(size - min);
+    let at = rng.usize(lo..hi + 1);
+
+    let (a, b) = if horizontally {
+        (
+            BspNode {
+                x: node.x,
+                y: node.y,
+                w: at,
+                h: node.h,
+                children: None,
+                room: None,
+            },
+            BspNode {
+                x: node.x + at,
+                y: node.y,
+                w: node.w - at,
+                h: node.h,
+                children: None,
+                room: None,
+            },
+        )
+    } else {
+        (
+            BspNode {
+                x: node.x,
+                y: node.y,
+                w: node.w,
+                h: at,
+                children: None,
+                room: None,
+            },
+            BspNode {
+                x: node.x,
+                y: node.y + at,
+                w: node.w,
+                h: node.h - at,
+                children: None,
+                room: None,
+            },
+        )
+    };
+    nodes.push(a);
+    nodes.push(b);
+    nodes[idx].children = Some((nodes.len() - 2, nodes.len() - 1));
+}
+
+fn random_size(leaf_size: usize, min: usize, max: usize, rng: &mut Rng) -> usize {
+    let hi = leaf_size.min(max);
+    let lo = ((leaf_size as f32 * ROOM_MIN_FILL) as usize).clamp(min, hi);
+    rng.usize(lo..hi + 1)
+}
+
+// rooms of all leafs below the node
+fn rooms_below(nodes: &[BspNode], idx: usize) -> Vec<usize> {
+    match nodes[idx].children {
+        Some((a, b)) => {
+            let mut rooms = rooms_below(nodes, a);
+            rooms.extend(rooms_below(nodes, b));
+            rooms
+        }
+        None => nodes[idx].room.into_iter().collect(),
+    }
+}
+
+// connects the closest pair of rooms between the two children of every node,
+// which results in all rooms being connected
+// if no corridor can be routed between the closest pair, the next closest is
+// tried
+fn connect_children(
+    level: &mut Level,
+    nodes: &[BspNode],
+    idx: usize,
+    params: &GeneratorParams,
+) -> Result<(), GenerateError> {
+    let Some((a, b)) = nodes[idx].children else {
+        return Ok(());
+    };
+    connect_children(level, nodes, a, params)?;
+    connect_children(level, nodes, b, params)?;
+
+    let mut pairs = vec![];
+    for i in rooms_below(nodes, a) {
+        for j in rooms_below(nodes, b) {
+            let dist = level.rooms[i].center().distance(level.rooms[j].center());
+            pairs.push((i, j, dist));
+        }
+    }
+    pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+    for &(i, j, _) in &pairs {
+        if connect_rooms(
+            level,
+            i,
+            j,
+            params.corridor_width,
+            GROUND,
+            CORRIDOR_WALL,
+            &params.tiles,
+        ) {
+            return Ok(());
+        }
+    }
+    match pairs.first() {
+        Some(&(_, j, _)) => Err(GenerateError::Disconnected { room: j }),
+        // one of the subtrees has no room
+        None => Ok(()),
+    }
+}
+
+impl LevelGenerator for BspGenerator {
+    fn generate(&self, params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError> {
+        params.validate()?;
+        let mut level = empty_level(params.width, params.height);
+
+        let mut nodes = vec![BspNode {
+            x: 0,
+            y: 0,
+            w: params.width,
+            h: params.height,
+            children: None,
+            room: None,
+        }];
+
+        // always split the biggest leaf, so the area is used evenly
+        let mut leaf_count = 1;
+        while leaf_count < params.rooms {
+            let biggest = (0..nodes.len())
+                .filter(|&i| nodes[i].children.is_none())
+                .filter(|&i| {
+                    nodes[i].can_split_horizontally(params) || nodes[i].can_split_vertically(params)
+                })
+                .max_by_key(|&i| (nodes[i].w * nodes[i].h, usize::MAX - i));
+            let Some(biggest) = biggest else {
+                // no leaf is big enough to hold two rooms
+                break;
+            };
+            split(&mut nodes, biggest, params, rng);
+            leaf_count += 1;
+        }
+
+        for leaf in nodes.iter_mut() {
+            if leaf.children.is_some() {
+                continue;
+            }
+            if leaf.w < params.room_min_width || leaf.h < params.room_min_height {
+                // level is too small for even a single room
+                continue;
+            }
+            if !params.prefabs.is_empty() && rng.f32() < params.prefab_chance {
+                if let Some(def) = random_prefab(params, leaf.w, leaf.h, rng) {
+                    let (w, h) = prefab::size(&def);
+                    let x = leaf.x + rng.usize(0..leaf.w - w + 1);
+                    let y = leaf.y + rng.usize(0..leaf.h - h + 1);
+                    if add_prefab(&mut level, &def, x, y, &params.tiles) {
+                        let idx = level.rooms.len() - 1;
+                        level.rooms[idx].connections.insert(idx, true);
+                        leaf.room = Some(idx);
+                        continue;
+                    }
+                }
+            }
+            let w = random_size(leaf.w, params.room_min_width, params.room_max_width, rng);
+            let h = random_size(leaf.h, params.room_min_height, params.room_max_height, rng);
+            let room = Room::new(
+                leaf.x + rng.usize(0..leaf.w - w + 1),
+                leaf.y + rng.usize(0..leaf.h - h + 1),
+                w,
+                h,
+            );
+            if add_room(&mut level, room, &params.tiles) {
+                let idx = level.rooms.len() - 1;
+                // every room is connected to itself
+                level.rooms[idx].connections.insert(idx, true);
+                leaf.room = Some(idx);
+            }
+        }
+
+        if level.rooms.len() < params.rooms {
+            return Err(GenerateError::NotEnoughSpace {
+                placed: level.rooms.len(),
+                rooms: params.rooms,
+            });
+        }
+
+        connect_children(&mut level, &nodes, 0, params)?;
+        add_loops(&mut level, params, rng);
+
+        Ok(level)
+    }
+}
@@ -0,0 +1,361 @@
+// CAVE LEVEL GENERATOR
+//
+// cellular automata: fills the level with random noise, then smooths it a few
+// times so that walls clump together into caves
+// the largest open squares of the cave become the rooms (chambers); where two
+// chambers next to each other in a minimum spanning tree lie in separate cave
+// regions a tunnel is dug between them
+// cave regions without a chamber are filled up again, and chambers whose
+// floor touches are neighbours in the room graph
+
+use std::collections::VecDeque;
+
+use fastrand::Rng;
+
+use super::{set_rooms_connected, spanning_tree, GeneratorParams, LevelGenerator, GROUND, WALL};
+use crate::game::{
+    error::GenerateError,
+    level::{Level, Room, Tile},
+};
+
+// chance of a tile being a wall in the initial noise
+const FILL_CHANCE: f32 = 0.45;
+const SMOOTH_ITERATIONS: usize = 5;
+// a tile becomes a wall with more, and floor with less wall neighbours
+const WALL_NEIGHBOURS: usize = 4;
+
+// free tiles kept around a chamber before the next one can be placed
+const CHAMBER_SPACING: usize = 2;
+
+pub struct CaveGenerator;
+
+// also used by the wfc generator to find chambers in its levels and join
+// its regions
+pub(super) struct Grid {
+    pub(super) width: usize,
+    pub(super) height: usize,
+    pub(super) walls: Vec<bool>,
+}
+
+impl Grid {
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return true;
+        }
+        self.walls[y as usize * self.width + x as usize]
+    }
+
+    fn wall_neighbours(&self, x: usize, y: usize) -> usize {
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx != 0 || dy != 0) && self.is_wall(x as i32 + dx, y as i32 + dy) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn smooth(&mut self) {
+        let mut walls = self.walls.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let n = self.wall_neighbours(x, y);
+                if n > WALL_NEIGHBOURS {
+                    walls[y * self.width + x] = true;
+                } else if n < WALL_NEIGHBOURS {
+                    walls[y * self.width + x] = false;
+                }
+            }
+        }
+        self.walls = walls;
+    }
+
+    // region index for every floor tile, None for walls
+    pub(super) fn regions(&self) -> Vec<Option<usize>> {
+        let mut regions = vec![None; self.walls.len()];
+        let mut region_count = 0;
+        for start in 0..self.walls.len() {
+            if self.walls[start] || regions[start].is_some() {
+                continue;
+            }
+            let mut stack = vec![start];
+            regions[start] = Some(region_count);
+            while let Some(idx) = stack.pop() {
+                let x = (idx % self.width) as i32;
+                let y = (idx / self.width) as i32;
+                for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                    if self.is_wall(nx, ny) {
+                        continue;
+                    }
+                    let n = ny as usize * self.width + nx as usize;
+                    if regions[n].is_none() {
+                        regions[n] = Some(region_count);
+                        stack.push(n);
+                    }
+                }
+            }
+            region_count += 1;
+        }
+        regions
+    }
+
+    // largest square of floor tiles that are not blocked, as (x, y, size)
+    fn largest_open_square(&self, blocked: &[bool]) -> Option<(usize, usize, usize)> {
+        // size of the largest square with its bottom right corner at a tile
+        let mut sizes = vec![0; self.walls.len()];
+        let mut best: Option<(usize, usize, usize)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = y * self.width + x;
+                if self.walls[idx] || blocked[idx] {
+                    continue;
+                }
+                let size = if x == 0 || y == 0 {
+                    1
+                } else {
+                    1 + sizes[idx - 1]
+                        .min(sizes[idx - self.width])
+                        .min(sizes[idx - self.width - 1])
+                };
+                sizes[idx] = size;
+                if best.is_none_or(|(_, _, best_size)| size > best_size) {
+                    best = Some((x + 1 - size, y + 1 - size, size));
+                }
+            }
+        }
+        best
+    }
+
+    // up to the given number of the largest open squares, spaced apart
+    // chambers are squares, at least as big as the smallest rooms
+    pub(super) fn chambers(&self, rooms: usize, params: &GeneratorParams) -> Vec<Room> {
+        let min_size = params.room_min_width.max(params.room_min_height);
+        let mut chambers: Vec<Room> = vec![];
+        let mut blocked = vec![false; self.walls.len()];
+        while chambers.len() < rooms {
+            let Some((x, y, size)) = self.largest_open_square(&blocked) else {
+                break;
+            };
+            if size < min_size {
+                break;
+            }
+            let y_end = (y + size + CHAMBER_SPACING).min(self.height);
+            let x_end = (x + size + CHAMBER_SPACING).min(self.width);
+            for by in y.saturating_sub(CHAMBER_SPACING)..y_end {
+                for bx in x.saturating_sub(CHAMBER_SPACING)..x_end {
+                    blocked[by * self.width + bx] = true;
+                }
+            }
+            chambers.push(Room::new(x, y, size, size));
+        }
+        chambers
+    }
+
+    // pairs of chambers whose floor touches: every floor tile belongs to the
+    // chamber it is closest to (walking), and two chambers are neighbours
+    // where their tiles meet
+    pub(super) fn chamber_graph(&self, chambers: &[Room]) -> Vec<(usize, usize)> {
+        let mut owners = vec![None; self.walls.len()];
+        let mut queue = VecDeque::new();
+        for (i, chamber) in chambers.iter().enumerate() {
+            for y in chamber.y..chamber.y + chamber.h {
+                for x in chamber.x..chamber.x + chamber.w {
+                    owners[y * self.width + x] = Some(i);
+                    queue.push_back(y * self.width + x);
+                }
+            }
+        }
+        let mut edges = vec![];
+        while let Some(idx) = queue.pop_front() {
+            let x = (idx % self.width) as i32;
+            let y = (idx / self.width) as i32;
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if self.is_wall(nx, ny) {
+                    continue;
+                }
+                let n = ny as usize * self.width + nx as usize;
+                match (owners[idx], owners[n]) {
+                    (owner, None) => {
+                        owners[n] = owner;
+                        queue.push_back(n);
+                    }
+                    (Some(a), Some(b)) if a < b => edges.push((a, b)),
+                    _ => {}
+                }
+            }
+        }
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    // digs a straight tunnel (first horizontal, then vertical) between two
+    // tiles, 2 tiles wide so that the player fits through
+    pub(super) fn dig(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let (mut x, mut y) = from;
+        loop {
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                // never dig into the outer border
+                let tx = (x + dx).clamp(1, self.width - 2);
+                let ty = (y + dy).clamp(1, self.height - 2);
+                self.walls[ty * self.width + tx] = false;
+            }
+            if x != to.0 {
+                if x < to.0 {
+                    x += 1
+                } else {
+                    x -= 1
+                }
+            } else if y != to.1 {
+                if y < to.1 {
+                    y += 1
+                } else {
+                    y -= 1
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn center_tile(room: &Room) -> (usize, usize) {
+    (room.x + room.w / 2, room.y + room.h / 2)
+}
+
+impl LevelGenerator for CaveGenerator {
+    fn generate(&self, params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError> {
+        params.validate()?;
+        let (width, height) = (params.width, params.height);
+        let mut grid = Grid {
+            width,
+            height,
+            walls: vec![true; width * height],
+        };
+
+        // noise, the outer border always stays wall
+        for y in 1..height.saturating_sub(1) {
+            for x in 1..width.saturating_sub(1) {
+                grid.walls[y * width + x] = rng.f32() < FILL_CHANCE;
+            }
+        }
+
+        for _ in 0..SMOOTH_ITERATIONS {
+            grid.smooth();
+        }
+
+        let chambers = grid.chambers(params.rooms, params);
+        if chambers.len() < params.rooms {
+            return Err(GenerateError::NotEnoughSpace {
+                placed: chambers.len(),
+                rooms: params.rooms,
+            });
+        }
+
+        // connect chambers, digging tunnels between separate regions
+        let edges = spanning_tree(&chambers);
+        let regions = grid.regions();
+        for &(i, j) in &edges {
+            let a = center_tile(&chambers[i]);
+            let b = center_tile(&chambers[j]);
+            if regions[a.1 * width + a.0] != regions[b.1 * width + b.0] {
+                grid.dig(a, b);
+            }
+        }
+
+        // keep only the region that contains the chambers
+        let regions = grid.regions();
+        if let Some(first) = chambers.first() {
+            let (x, y) = center_tile(first);
+            let main_region = regions[y * width + x];
+            for (idx, region) in regions.iter().enumerate() {
+                if region.is_some() && *region != main_region {
+                    grid.walls[idx] = true;
+                }
+            }
+        }
+
+        let mut level = Level {
+            width,
+            height,
+            tiles: grid
+                .walls
+                .iter()
+                .map(|&wall| Tile::from_char(if wall { WALL } else { GROUND }, &params.tiles))
+                .collect(),
+            rooms: chambers,
+            start_room: None,
+            goal_room: None,
+            keys: vec![],
+            secret_doors: vec![],
+        };
+        for i in 0..level.rooms.len() {
+            // every room is connected to itself
+            level.rooms[i].connections.insert(i, true);
+        }
+        for (i, j) in grid.chamber_graph(&level.rooms) {
+            set_rooms_connected(&mut level, i, j);
+        }
+
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::level_generator::tests::params_with_tiles;
+
+    fn grid(rows: &[&str]) -> Grid {
+        Grid {
+            width: rows[0].len(),
+            height: rows.len(),
+            walls: rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|ch| ch == '#')
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn chambers_are_neighbours_where_their_floor_touches() {
+        let grid = grid(&[
+            "#############",
+            "#...#...#...#",
+            "#...#...#...#",
+            "#...#...#...#",
+            "#...........#",
+            "#############",
+        ]);
+        let chambers = [
+            Room::new(1, 1, 3, 3),
+            Room::new(5, 1, 3, 3),
+            Room::new(9, 1, 3, 3),
+        ];
+        assert_eq!(grid.chamber_graph(&chambers), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn room_graph_reaches_every_room() {
+        let params = params_with_tiles(60, 60, 8);
+        for seed in 0..5 {
+            let level = CaveGenerator
+                .generate(&params, &mut Rng::with_seed(seed))
+                .unwrap();
+            let mut reached = vec![0];
+            let mut i = 0;
+            while i < reached.len() {
+                for &room in level.rooms[reached[i]].connections.keys() {
+                    if !reached.contains(&room) {
+                        reached.push(room);
+                    }
+                }
+                i += 1;
+            }
+            assert_eq!(reached.len(), level.rooms.len(), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// CORRIDOR ROUTING
+//
+// finds the path of a corridor between two rooms with A* over the tiles of
+// the level
+// the corridor may go through empty space, other corridors and the floor and
+// walls of the two rooms it connects, but never through any other room
+// (including its walls)
+// turning costs extra, so corridors stay mostly straight
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use macroquad::math::vec2;
+
+use crate::game::level::Level;
+
+// extra cost of changing direction, compared to a cost of 1 per tile
+const TURN_COST: usize = 2;
+
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+// index of the room that covers each tile, None for tiles outside of rooms
+pub fn room_owners(level: &Level) -> Vec<Option<usize>> {
+    let mut owners = vec![None; level.tiles.len()];
+    for (i, room) in level.rooms.iter().enumerate() {
+        for y in room.y..room.y + room.h {
+            for x in room.x..room.x + room.w {
+                owners[y * level.width + x] = Some(i);
+            }
+        }
+    }
+    owners
+}
+
+// walkable tile of a room closest to its center, where corridors start and
+// end
+pub fn anchor(level: &Level, room: usize) -> (usize, usize) {
+    let room = &level.rooms[room];
+    let center = room.center();
+    let mut best = (center.x.floor() as usize, center.y.floor() as usize);
+    let mut best_dist = None;
+    for y in room.y..room.y + room.h {
+        for x in room.x..room.x + room.w {
+            if level.tiles[y * level.width + x].solid {
+                continue;
+            }
+            let dist = center.distance(vec2(x as f32 + 0.5, y as f32 + 0.5));
+            if best_dist.is_none_or(|best_dist| dist < best_dist) {
+                best = (x, y);
+                best_dist = Some(dist);
+            }
+        }
+    }
+    best
+}
+
+// a corridor of the given width occupies the square with its top left corner
+// at the path tile
+fn is_passable(
+    level: &Level,
+    owners: &[Option<usize>],
+    rooms: (usize, usize),
+    x: i32,
+    y: i32,
+    width: usize,
+) -> bool {
+    for dy in 0..width as i32 {
+        for dx in 0..width as i32 {
+            let (tx, ty) = (x + dx, y + dy);
+            if tx < 0 || ty < 0 || tx >= level.width as i32 || ty >= level.height as i32 {
+                return false;
+            }
+            let idx = ty as usize * level.width + tx as usize;
+            match owners[idx] {
+                Some(owner) if owner != rooms.0 && owner != rooms.1 => return false,
+                // inside the rooms only walk on floor, so the corridor ends
+                // at the room wall instead of cutting through its insides
+                Some(owner)
+                    if level.tiles[idx].solid
+                        && !level.rooms[owner].is_border(tx as usize, ty as usize) =>
+                {
+                    return false
+                }
+                _ => {}
+            }
+        }
+    }
+    true
+}
+
+// tiles of the cheapest corridor from one tile to another, both included,
+// or None if the rooms cannot be connected without cutting through others
+pub fn find_path(
+    level: &Level,
+    owners: &[Option<usize>],
+    rooms: (usize, usize),
+    from: (usize, usize),
+    to: (usize, usize),
+    width: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let idx = |x: usize, y: usize| y * level.width + x;
+    let heuristic = |x: usize, y: usize| x.abs_diff(to.0) + y.abs_diff(to.1);
+
+    // search states are tile and direction of arrival, so that turns can be
+    // priced in
+    let state = |tile: usize, dir: usize| tile * DIRECTIONS.len() + dir;
+    let mut costs = vec![usize::MAX; level.tiles.len() * DIRECTIONS.len()];
+    let mut came_from = vec![usize::MAX; level.tiles.len() * DIRECTIONS.len()];
+    let mut open = BinaryHeap::new();
+
+    let start = idx(from.0, from.1);
+    for dir in 0..DIRECTIONS.len() {
+        costs[state(start, dir)] = 0;
+        open.push(Reverse((heuristic(from.0, from.1), 0, state(start, dir))));
+    }
+
+    while let Some(Reverse((_, cost, current))) = open.pop() {
+        if cost > costs[current] {
+            // a cheaper way to this state was found already
+            continue;
+        }
+        let tile = current / DIRECTIONS.len();
+        let dir = current % DIRECTIONS.len();
+        let (x, y) = (tile % level.width, tile / level.width);
+        if (x, y) == to {
+            let mut path = vec![(x, y)];
+            let mut current = current;
+            while came_from[current] != usize::MAX {
+                current = came_from[current];
+                let tile = current / DIRECTIONS.len();
+                path.push((tile % level.width, tile / level.width));
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for (next_dir, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if !is_passable(level, owners, rooms, nx, ny, width) {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            let turn = if next_dir == dir || tile == start {
+                0
+            } else {
+                TURN_COST
+            };
+            let next_cost = cost + 1 + turn;
+            let next = state(idx(nx, ny), next_dir);
+            if next_cost < costs[next] {
+                costs[next] = next_cost;
+                came_from[next] = current;
+                open.push(Reverse((next_cost + heuristic(nx, ny), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room,
+        level_generator::{add_room, empty_level},
+        resources::tests::resources,
+    };
+
+    fn level_with_rooms(rooms: Vec<Room>) -> Level {
+        let res = resources();
+        let mut level = empty_level(30, 20);
+        for room in rooms {
+            assert!(add_room(&mut level, room, &res.tile_defintions));
+        }
+        level
+    }
+
+    #[test]
+    fn paths_go_around_other_rooms() {
+        // a room right between the two that get connected
+        let level = level_with_rooms(vec![
+            Room::new(0, 5, 5, 5),
+            Room::new(25, 5, 5, 5),
+            Room::new(10, 2, 10, 12),
+        ]);
+        let owners = room_owners(&level);
+        let path = find_path(&level, &owners, (0, 1), (2, 7), (27, 7), 1).unwrap();
+        assert_eq!(path.first(), Some(&(2, 7)));
+        assert_eq!(path.last(), Some(&(27, 7)));
+        for (x, y) in &path {
+            assert_ne!(owners[y * level.width + x], Some(2));
+        }
+        for pair in path.windows(2) {
+            let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+            assert_eq!(ax.abs_diff(bx) + ay.abs_diff(by), 1);
+        }
+    }
+
+    #[test]
+    fn no_path_through_a_room_that_blocks_everything() {
+        let level = level_with_rooms(vec![
+            Room::new(0, 5, 5, 5),
+            Room::new(25, 5, 5, 5),
+            Room::new(10, 0, 5, 20),
+        ]);
+        let owners = room_owners(&level);
+        assert!(find_path(&level, &owners, (0, 1), (2, 7), (27, 7), 1).is_none());
+    }
+
+    #[test]
+    fn straight_paths_are_preferred() {
+        let level = level_with_rooms(vec![Room::new(0, 5, 5, 5), Room::new(25, 5, 5, 5)]);
+        let owners = room_owners(&level);
+        let path = find_path(&level, &owners, (0, 1), (2, 7), (27, 7), 1).unwrap();
+        assert!(path.iter().all(|&(_, y)| y == 7));
+    }
+}
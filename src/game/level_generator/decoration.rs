@@ -0,0 +1,212 @@
+// DECORATION
+//
+// post processing of generated levels
+// scatters the decorations of their biomes over the floor of rooms, more of
+// them in treasure and secret rooms, and
+// replaces walls with the variants from tiles.json that match the walkable
+// tiles around them, so that faces, sides and corners of walls look
+// different
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+use super::GeneratorParams;
+use crate::game::{
+    level::{Level, RoomRole, Tile},
+    resources::{TileDefinition, NEIGHBOURS},
+};
+
+// chance of a room floor tile getting a decoration
+pub const DEFAULT_DECORATION_DENSITY: f32 = 0.05;
+// density in treasure and secret rooms, relative to the others
+const TREASURE_DENSITY_FACTOR: f32 = 4.;
+
+pub fn scatter_decorations(level: &mut Level, params: &GeneratorParams, rng: &mut Rng) {
+    for room in &level.rooms {
+        // prefabs are decorated by hand
+        if room.prefab.is_some() {
+            continue;
+        }
+        let Some(biome) = room
+            .biome
+            .as_ref()
+            .and_then(|name| params.biomes.iter().find(|biome| biome.name == *name))
+        else {
+            continue;
+        };
+        if biome.decorations.is_empty() {
+            continue;
+        }
+        let density = match room.role {
+            RoomRole::Treasure | RoomRole::Secret => {
+                params.decoration_density * TREASURE_DENSITY_FACTOR
+            }
+            _ => params.decoration_density,
+        };
+        for y in room.y..room.y + room.h {
+            for x in room.x..room.x + room.w {
+                let idx = y * level.width + x;
+                if room.is_border(x, y)
+                    || level.tiles[idx].ch != biome.floor
+                    || rng.f32() >= density
+                {
+                    continue;
+                }
+                let ch = biome.decorations[rng.usize(0..biome.decorations.len())];
+                level.tiles[idx] = Tile::from_char(ch, &params.tiles);
+            }
+        }
+    }
+}
+
+fn mask_of(names: &[String]) -> u8 {
+    NEIGHBOURS
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _, _))| names.iter().any(|n| n == name))
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+// bits of the walkable neighbours, in the order of NEIGHBOURS
+// tiles outside of the level count as solid, diagonal neighbours only count
+// when both tiles next to them are solid, so that only outer corners see
+// them
+fn open_mask(level: &Level, x: usize, y: usize) -> u8 {
+    let is_open = |dx: i32, dy: i32| {
+        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+        nx >= 0
+            && ny >= 0
+            && (nx as usize) < level.width
+            && (ny as usize) < level.height
+            && !level.tiles[ny as usize * level.width + nx as usize].solid
+    };
+    let mut mask = 0;
+    for (i, (_, dx, dy)) in NEIGHBOURS.iter().enumerate() {
+        let diagonal = *dx != 0 && *dy != 0;
+        if diagonal && (is_open(*dx, 0) || is_open(0, *dy)) {
+            continue;
+        }
+        if is_open(*dx, *dy) {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+// replaces walls with the variant that has their exact mask, walls without
+// a matching variant stay as they are
+pub fn autotile_walls(level: &mut Level, tiles: &HashMap<char, TileDefinition>) {
+    // (wall, mask) -> variant
+    let mut variants = HashMap::new();
+    for def in tiles.values() {
+        if let Some(wall) = def.variant_of {
+            for mask in &def.masks {
+                variants.insert((wall, mask_of(mask)), def.ch);
+            }
+        }
+    }
+    if variants.is_empty() {
+        return;
+    }
+
+    // masks only depend on solidity, which variants keep, so replacing in
+    // place does not change the masks of later tiles
+    for idx in 0..level.tiles.len() {
+        let (x, y) = (idx % level.width, idx / level.width);
+        let ch = level.tiles[idx].ch;
+        if let Some(&variant) = variants.get(&(ch, open_mask(level, x, y))) {
+            level.tiles[idx] = Tile::from_char(variant, tiles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room,
+        level_generator::tests::params_with_tiles,
+        resources::{
+            tests::{level_from_rows, resources},
+            BiomeDefinition,
+        },
+    };
+
+    // a corridor of two floor tiles
+    const ROWS: [&str; 3] = ["aaaa", "acca", "aaaa"];
+
+    fn bits(names: &[&str]) -> u8 {
+        mask_of(
+            &names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn masks_have_the_bits_of_their_neighbours() {
+        assert_eq!(bits(&[]), 0);
+        assert_eq!(bits(&["n", "s"]), 0b1_0001);
+        assert_eq!(bits(&["nw"]), 0b1000_0000);
+    }
+
+    #[test]
+    fn diagonals_only_count_at_outer_corners() {
+        let level = level_from_rows(&ROWS, vec![]);
+        assert_eq!(open_mask(&level, 0, 0), bits(&["se"]));
+        assert_eq!(open_mask(&level, 0, 1), bits(&["e"]));
+        // the floor to the north east does not count, the one to the north
+        // is next to it
+        assert_eq!(open_mask(&level, 1, 2), bits(&["n"]));
+        assert_eq!(open_mask(&level, 1, 1), bits(&["e"]));
+    }
+
+    #[test]
+    fn walls_get_the_variant_of_their_mask() {
+        let mut level = level_from_rows(&ROWS, vec![]);
+        autotile_walls(&mut level, &resources().tile_defintions);
+        // faces above, sides next to and corners around the floor, nothing
+        // matches a wall with floor only to the north
+        assert_eq!(level.to_rows(), vec!["pnnp", "occo", "paap"]);
+    }
+
+    fn decorated(density: f32) -> Vec<String> {
+        let mut params = params_with_tiles(7, 7, 1);
+        params.decoration_density = density;
+        params.biomes.push(BiomeDefinition {
+            name: "Crypt".to_string(),
+            wall: 'a',
+            corridor_wall: 'b',
+            floor: 'c',
+            corridor: 'c',
+            decorations: vec!['e', 'f'],
+            enemies: vec![],
+        });
+        let mut room = Room::new(0, 0, 7, 7);
+        room.biome = Some("Crypt".to_string());
+        let rows = [
+            "aaaaaaa", "accccca", "accccca", "accccca", "accccca", "accccca", "aaaaaaa",
+        ];
+        let mut level = level_from_rows(&rows, vec![room]);
+        scatter_decorations(&mut level, &params, &mut Rng::with_seed(1));
+        level.to_rows()
+    }
+
+    #[test]
+    fn decorations_follow_the_density() {
+        let rows = decorated(0.);
+        assert!(rows.iter().all(|row| !row.contains(['e', 'f'])));
+
+        let rows = decorated(1.);
+        for row in &rows[1..6] {
+            assert!(
+                row[1..6].chars().all(|ch| ch == 'e' || ch == 'f'),
+                "{}",
+                row
+            );
+        }
+        assert_eq!(rows[0], "aaaaaaa");
+    }
+}
@@ -0,0 +1,217 @@
+// DUNGEONS
+//
+// a dungeon is a stack of generated floors connected by stairs
+// every floor but the first has stairs up in its start room, every floor but
+// the last has stairs down in its goal room, where the last floor has the
+// nexus
+// deeper floors spend a bigger difficulty budget on every room and use the
+// next biome
+// each floor has its own seed derived from the seed of the run, so a floor
+// is the same no matter when it is generated
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+use super::{
+    biome::apply_biomes,
+    corridor::anchor,
+    decoration::{autotile_walls, scatter_decorations},
+    endpoints::{choose_start_and_goal, EndpointParams},
+    generate_with_retries,
+    locks::{add_doors_and_locks, LockParams},
+    population::{level_definition, PopulationParams, DEFAULT_ROOM_BUDGET},
+    roles::assign_roles,
+    FailedSeeds, GeneratorParams, LevelGenerator,
+};
+use crate::game::{
+    error::GenerateError,
+    floor::{STAIRS_DOWN, STAIRS_UP},
+    level::{Level, Tile},
+    resources::{LevelDefinition, Resources, TileDefinition},
+};
+
+// extra difficulty points per room for every floor below the first
+pub const DEFAULT_BUDGET_PER_FLOOR: usize = 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DungeonParams {
+    pub floors: usize,
+    // locked rooms on every floor
+    pub locks: usize,
+    pub budget_per_floor: usize,
+    pub endpoints: EndpointParams,
+}
+
+impl DungeonParams {
+    pub fn new(floors: usize, locks: usize) -> Self {
+        Self {
+            floors,
+            locks,
+            budget_per_floor: DEFAULT_BUDGET_PER_FLOOR,
+            endpoints: EndpointParams::default(),
+        }
+    }
+}
+
+// seed of a floor, the first floor uses the seed of the run itself
+pub fn floor_seed(seed: u64, floor: usize) -> u64 {
+    let mut rng = Rng::with_seed(seed);
+    let mut seed = seed;
+    for _ in 0..floor {
+        seed = rng.u64(..);
+    }
+    seed
+}
+
+// puts stairs on the walkable tiles closest to the room centers, where the
+// player starts and the nexus would be
+pub fn add_stairs(
+    level: &mut Level,
+    start_room: usize,
+    goal_room: usize,
+    up: bool,
+    down: bool,
+    tiles: &HashMap<char, TileDefinition>,
+) {
+    let (x, y) = anchor(level, goal_room);
+    let goal = y * level.width + x;
+    let (x, y) = anchor(level, start_room);
+    let start = y * level.width + x;
+    if down {
+        level.tiles[goal] = Tile::from_char(STAIRS_DOWN, tiles);
+    }
+    // with a single room, going down wins over going back up
+    if up && !(down && start == goal) {
+        level.tiles[start] = Tile::from_char(STAIRS_UP, tiles);
+    }
+}
+
+// generates a floor with stairs, doors and enemies
+// returns the level and its definition together with the seed it was
+// generated from and the seeds that failed before it
+pub fn generate_floor(
+    generator: &dyn LevelGenerator,
+    params: &GeneratorParams,
+    dungeon: &DungeonParams,
+    floor: usize,
+    seed: u64,
+    res: &Resources,
+) -> Result<(Level, LevelDefinition, u64, FailedSeeds), GenerateError> {
+    let (mut lvl, seed, mut rng, failed) = generate_with_retries(generator, params, seed)?;
+    let (start_room, goal_room) = choose_start_and_goal(&mut lvl, &dungeon.endpoints)?;
+    assign_roles(&mut lvl, params.secret_chance, &mut rng);
+    apply_biomes(&mut lvl, params, floor, &mut rng);
+    scatter_decorations(&mut lvl, params, &mut rng);
+    // before doors are put into the doorways, which are solid until opened
+    autotile_walls(&mut lvl, &params.tiles);
+    let last = floor + 1 >= dungeon.floors;
+    add_stairs(
+        &mut lvl,
+        start_room,
+        goal_room,
+        floor > 0,
+        !last,
+        &params.tiles,
+    );
+    add_doors_and_locks(
+        &mut lvl,
+        start_room,
+        goal_room,
+        &LockParams::new(dungeon.locks),
+        &params.tiles,
+        &mut rng,
+    );
+    let mut population = PopulationParams::new(start_room, goal_room);
+    population.room_budget = DEFAULT_ROOM_BUDGET + floor * dungeon.budget_per_floor;
+    population.nexus = last;
+    let lvl_def = level_definition(&lvl, &population, &params.biomes, res, &mut rng);
+    Ok((lvl, lvl_def, seed, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::TILE_SIZE,
+        level_generator::{
+            generator_by_name, locks::reachable_tiles, tests::game_params, GENERATOR_NAMES,
+        },
+        resources::tests::resources,
+    };
+
+    fn tile(x: f32, y: f32) -> (usize, usize) {
+        ((x / TILE_SIZE) as usize, (y / TILE_SIZE) as usize)
+    }
+
+    // walks the level like a player would: picks up every key that can be
+    // reached, opens the doors of the keys, repeats, and returns whether all
+    // keys were found and the nexus can be reached in the end
+    fn is_solvable(lvl: &Level, lvl_def: &LevelDefinition) -> bool {
+        let start = tile(lvl_def.player.x, lvl_def.player.y);
+        let mut keys = vec![];
+        loop {
+            let reachable = reachable_tiles(lvl, start, &keys);
+            let found: Vec<usize> = lvl_def
+                .keys
+                .iter()
+                .filter(|key| !keys.contains(&key.lock))
+                .filter(|key| {
+                    let (x, y) = tile(key.x, key.y);
+                    reachable[y * lvl.width + x]
+                })
+                .map(|key| key.lock)
+                .collect();
+            if found.is_empty() {
+                let nexus = lvl_def.enemies.iter().find(|e| e.name == "Nexus").unwrap();
+                let (x, y) = tile(nexus.x, nexus.y);
+                return keys.len() == lvl_def.keys.len() && reachable[y * lvl.width + x];
+            }
+            keys.extend(found);
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_floor() {
+        let res = resources();
+        let mut params = game_params(&res);
+        params.height = 60;
+        params.rooms = 8;
+        params.biome_per_room = true;
+        let dungeon = DungeonParams::new(3, 2);
+        for name in GENERATOR_NAMES {
+            let generator = generator_by_name(name).unwrap();
+            let floor = || {
+                let (lvl, lvl_def, seed, _) =
+                    generate_floor(generator.as_ref(), &params, &dungeon, 1, 5, &res).unwrap();
+                let roles: Vec<_> = lvl.rooms.iter().map(|room| room.role).collect();
+                let biomes: Vec<_> = lvl.rooms.iter().map(|room| room.biome.clone()).collect();
+                (
+                    serde_json::to_string(&lvl_def).unwrap(),
+                    roles,
+                    biomes,
+                    seed,
+                )
+            };
+            assert!(floor() == floor(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn locked_levels_are_solvable() {
+        let res = resources();
+        let mut params = game_params(&res);
+        params.height = 60;
+        params.rooms = 8;
+        let dungeon = DungeonParams::new(1, 3);
+        for name in GENERATOR_NAMES {
+            let generator = generator_by_name(name).unwrap();
+            for seed in 0..3 {
+                let (lvl, lvl_def, _, _) =
+                    generate_floor(generator.as_ref(), &params, &dungeon, 0, seed, &res).unwrap();
+                assert_eq!(lvl_def.keys.len(), 3);
+                assert!(is_solvable(&lvl, &lvl_def), "{} seed {}", name, seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// START AND GOAL
+//
+// picks the rooms where the player starts and where the goal is, as the two
+// rooms farthest apart when walking over the floor, so that the goal is not
+// right next to the start
+// optionally the goal has to be in a dead end, and the rooms a minimum
+// distance apart
+
+use super::{
+    corridor::anchor,
+    metrics::{dead_end_rooms, tile_distances},
+};
+use crate::game::{error::GenerateError, level::Level};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EndpointParams {
+    // in tiles, walking
+    pub min_distance: usize,
+    pub goal_in_dead_end: bool,
+}
+
+// walking distance between the anchors of every pair of rooms, None where
+// one cannot be reached from the other
+pub fn room_distances(level: &Level) -> Vec<Vec<Option<usize>>> {
+    let anchors: Vec<(usize, usize)> = (0..level.rooms.len())
+        .map(|room| anchor(level, room))
+        .collect();
+    anchors
+        .iter()
+        .map(|&from| {
+            let distances = tile_distances(level, from);
+            anchors
+                .iter()
+                .map(|&(x, y)| distances[y * level.width + x])
+                .collect()
+        })
+        .collect()
+}
+
+// marks the chosen rooms on the level and returns them as (start, goal)
+pub fn choose_start_and_goal(
+    level: &mut Level,
+    params: &EndpointParams,
+) -> Result<(usize, usize), GenerateError> {
+    let distances = room_distances(level);
+    let goals = match params.goal_in_dead_end {
+        true => dead_end_rooms(level),
+        false => (0..level.rooms.len()).collect(),
+    };
+
+    // farthest pair, the lowest indexes on ties
+    let mut best: Option<(usize, usize, usize)> = None;
+    for (start, from_start) in distances.iter().enumerate() {
+        for &goal in &goals {
+            // a single room is start and goal at once
+            if goal == start && level.rooms.len() > 1 {
+                continue;
+            }
+            let Some(dist) = from_start[goal] else {
+                continue;
+            };
+            if dist >= params.min_distance && best.is_none_or(|(_, _, best)| dist > best) {
+                best = Some((start, goal, dist));
+            }
+        }
+    }
+
+    let (start, goal, _) = best.ok_or(GenerateError::NoStartAndGoal {
+        min_distance: params.min_distance,
+        goal_in_dead_end: params.goal_in_dead_end,
+    })?;
+    level.start_room = Some(start);
+    level.goal_room = Some(goal);
+    Ok((start, goal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room, level_generator::set_rooms_connected, resources::tests::level_from_rows,
+    };
+
+    // three rooms in a row, joined by doors
+    const ROWS: [&str; 5] = [
+        "aaaaaaaaaaaaa",
+        "acccacccaccca",
+        "acccgcccgccca",
+        "acccacccaccca",
+        "aaaaaaaaaaaaa",
+    ];
+
+    fn level(rooms: usize) -> Level {
+        let mut level = level_from_rows(
+            &ROWS,
+            (0..rooms)
+                .map(|room| Room::new(room * 4, 0, 5, 5))
+                .collect(),
+        );
+        for room in 1..rooms {
+            set_rooms_connected(&mut level, room - 1, room);
+        }
+        level
+    }
+
+    #[test]
+    fn distances_between_all_rooms() {
+        let distances = room_distances(&level(3));
+        assert_eq!(distances[0], vec![Some(0), Some(4), Some(8)]);
+        assert_eq!(distances[2][1], Some(4));
+    }
+
+    #[test]
+    fn start_and_goal_are_farthest_apart() {
+        let mut level = level(3);
+        let ends = choose_start_and_goal(&mut level, &EndpointParams::default());
+        assert_eq!(ends, Ok((0, 2)));
+        assert_eq!(level.start_room, Some(0));
+        assert_eq!(level.goal_room, Some(2));
+    }
+
+    #[test]
+    fn a_single_room_is_start_and_goal() {
+        let mut level = level(1);
+        let ends = choose_start_and_goal(&mut level, &EndpointParams::default());
+        assert_eq!(ends, Ok((0, 0)));
+    }
+
+    #[test]
+    fn no_start_and_goal_without_a_pair_that_qualifies() {
+        let params = EndpointParams {
+            min_distance: 9,
+            goal_in_dead_end: false,
+        };
+        let result = choose_start_and_goal(&mut level(3), &params);
+        assert!(matches!(result, Err(GenerateError::NoStartAndGoal { .. })));
+
+        // a ring has no dead ends
+        let mut level = level(3);
+        set_rooms_connected(&mut level, 2, 0);
+        let params = EndpointParams {
+            min_distance: 0,
+            goal_in_dead_end: true,
+        };
+        let result = choose_start_and_goal(&mut level, &params);
+        assert!(matches!(result, Err(GenerateError::NoStartAndGoal { .. })));
+        assert_eq!(level.goal_room, None);
+    }
+}
@@ -0,0 +1,238 @@
+// DOORS AND LOCKS
+//
+// puts doors into the doorways of rooms and locks some of the rooms, the
+// goal room always being the last one to unlock
+// the key of every lock is placed in a room that can be reached from the
+// start room, walking over the tiles, without passing a door that is locked
+// with a key that is not found yet, so the level can always be finished
+// (the room graph is not enough for that, in caves a locked chamber can be
+// the only way from one part of the level to another)
+// keys go to treasure rooms where possible and never to secret rooms, which
+// get secret doors instead of locks, and onto the key spots of the room when
+// its prefab has reachable ones
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+use macroquad::math::vec2;
+
+use super::{corridor::anchor, metrics::flood_fill};
+use crate::game::{
+    door::{lock_of, Key, DOOR, LOCKED_DOORS},
+    level::{Level, RoomRole, Tile, TILE_SIZE},
+    resources::TileDefinition,
+};
+
+pub const DEFAULT_DOOR_CHANCE: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LockParams {
+    // number of locked rooms, limited by the number of locked door kinds
+    // and rooms other than the start room
+    pub locks: usize,
+    // chance of the doorways of a room that is not locked getting doors
+    pub door_chance: f32,
+}
+
+impl LockParams {
+    pub fn new(locks: usize) -> Self {
+        Self {
+            locks,
+            door_chance: DEFAULT_DOOR_CHANCE,
+        }
+    }
+}
+
+// tiles in the walls of a room where corridors enter
+fn doorways(level: &Level, room: usize) -> Vec<usize> {
+    let room = &level.rooms[room];
+    let mut tiles = vec![];
+    for y in room.y..room.y + room.h {
+        for x in room.x..room.x + room.w {
+            let idx = y * level.width + x;
+            if room.is_border(x, y) && !level.tiles[idx].solid {
+                tiles.push(idx);
+            }
+        }
+    }
+    tiles
+}
+
+// tiles that can be walked to from the given tile when holding the keys of
+// the given locks, doors that are not locked (secret ones included) can be
+// opened by everyone
+pub fn reachable_tiles(level: &Level, from: (usize, usize), keys: &[usize]) -> Vec<bool> {
+    let mut secret = vec![false; level.tiles.len()];
+    for &idx in &level.secret_doors {
+        secret[idx] = true;
+    }
+    let passable = |idx: usize| {
+        let tile = &level.tiles[idx];
+        !tile.solid
+            || tile.ch == DOOR
+            || secret[idx]
+            || lock_of(tile.ch).is_some_and(|lock| keys.contains(&lock))
+    };
+
+    let start = from.1 * level.width + from.0;
+    flood_fill(level, &[start], passable)
+        .iter()
+        .map(Option::is_some)
+        .collect()
+}
+
+// walkable tiles inside the walls of the room that are reachable
+fn reachable_inner_tiles(level: &Level, room: usize, reachable: &[bool]) -> Vec<(usize, usize)> {
+    let room = &level.rooms[room];
+    let mut tiles = vec![];
+    for y in room.y..room.y + room.h {
+        for x in room.x..room.x + room.w {
+            let idx = y * level.width + x;
+            if !room.is_border(x, y) && !level.tiles[idx].solid && reachable[idx] {
+                tiles.push((x, y));
+            }
+        }
+    }
+    tiles
+}
+
+// closes the doorways of the room with walls that open like doors, each
+// looking like the wall next to it
+fn add_secret_doors(level: &mut Level, room: usize) {
+    for idx in doorways(level, room) {
+        let x = idx % level.width;
+        let r = &level.rooms[room];
+        let along_wall = if x == r.x || x == r.x + r.w - 1 {
+            [level.tile_index_above(idx), level.tile_index_below(idx)]
+        } else {
+            [level.tile_index_left(idx), level.tile_index_right(idx)]
+        };
+        let wall = along_wall
+            .into_iter()
+            .flatten()
+            .find(|&n| level.tiles[n].solid && r.is_border(n % level.width, n / level.width));
+        if let Some(wall) = wall {
+            level.tiles[idx] = level.tiles[wall].clone();
+            level.secret_doors.push(idx);
+        }
+    }
+}
+
+pub fn add_doors_and_locks(
+    level: &mut Level,
+    start_room: usize,
+    goal_room: usize,
+    params: &LockParams,
+    tiles: &HashMap<char, TileDefinition>,
+    rng: &mut Rng,
+) {
+    // lock of every room, the goal room gets the last one
+    let mut candidates: Vec<usize> = (0..level.rooms.len())
+        .filter(|&room| room != start_room && room != goal_room)
+        .filter(|&room| level.rooms[room].role != RoomRole::Secret)
+        .collect();
+    rng.shuffle(&mut candidates);
+    if goal_room != start_room {
+        candidates.insert(0, goal_room);
+    }
+    let locks = params.locks.min(LOCKED_DOORS.len()).min(candidates.len());
+    let mut room_locks: Vec<Option<usize>> = vec![None; level.rooms.len()];
+    for (i, &room) in candidates.iter().take(locks).enumerate() {
+        room_locks[room] = Some(locks - 1 - i);
+    }
+
+    for (room, &room_lock) in room_locks.iter().enumerate() {
+        if level.rooms[room].role == RoomRole::Secret {
+            add_secret_doors(level, room);
+            continue;
+        }
+        let ch = match room_lock {
+            Some(lock) => LOCKED_DOORS[lock],
+            None if room != start_room && rng.f32() < params.door_chance => DOOR,
+            None => continue,
+        };
+        for idx in doorways(level, room) {
+            level.tiles[idx] = Tile::from_char(ch, tiles);
+        }
+    }
+
+    // keys go where the doors are, so that walls of locked doors are taken
+    // into account
+    let start = anchor(level, start_room);
+    for lock in 0..locks {
+        // with the keys of all lower locks found
+        let keys: Vec<usize> = (0..lock).collect();
+        let reachable = reachable_tiles(level, start, &keys);
+        let mut rooms: Vec<usize> = (0..level.rooms.len())
+            // rather not right where the player starts
+            .filter(|&room| room != start_room && level.rooms[room].role != RoomRole::Secret)
+            .filter(|&room| !reachable_inner_tiles(level, room, &reachable).is_empty())
+            .collect();
+        let treasure: Vec<usize> = rooms
+            .iter()
+            .copied()
+            .filter(|&room| level.rooms[room].role == RoomRole::Treasure)
+            .collect();
+        if !treasure.is_empty() {
+            rooms = treasure;
+        } else if rooms.is_empty() {
+            rooms = vec![start_room];
+        }
+        let room = rooms[rng.usize(0..rooms.len())];
+        let mut tiles = reachable_inner_tiles(level, room, &reachable);
+        let spots: Vec<(usize, usize)> = level.rooms[room]
+            .key_spots
+            .iter()
+            .copied()
+            .filter(|spot| tiles.contains(spot))
+            .collect();
+        if !spots.is_empty() {
+            tiles = spots;
+        }
+        let (x, y) = if tiles.is_empty() {
+            start
+        } else {
+            tiles[rng.usize(0..tiles.len())]
+        };
+        level.keys.push(Key {
+            lock,
+            pos: vec2(x as f32 + 0.5, y as f32 + 0.5) * TILE_SIZE,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room,
+        resources::tests::{level_from_rows, resources},
+    };
+
+    // three rooms in a row, joined by open doorways
+    const ROWS: [&str; 5] = [
+        "aaaaaaaaaaaaa",
+        "acccacccaccca",
+        "accccccccccca",
+        "acccacccaccca",
+        "aaaaaaaaaaaaa",
+    ];
+
+    #[test]
+    fn keys_go_to_the_key_spots_of_the_room() {
+        let tiles = resources().tile_defintions;
+        for seed in 0..10 {
+            let rooms = (0..3).map(|room| Room::new(room * 4, 0, 5, 5)).collect();
+            let mut level = level_from_rows(&ROWS, rooms);
+            level.rooms[1].role = RoomRole::Treasure;
+            // the second one is behind the locked goal room
+            level.rooms[1].key_spots = vec![(6, 1)];
+            level.rooms[2].key_spots = vec![(10, 3)];
+
+            let params = LockParams::new(1);
+            add_doors_and_locks(&mut level, 0, 2, &params, &tiles, &mut Rng::with_seed(seed));
+            assert_eq!(level.keys.len(), 1);
+            assert_eq!(level.keys[0].pos, vec2(6.5, 1.5) * TILE_SIZE);
+        }
+    }
+}
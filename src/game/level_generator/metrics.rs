@@ -0,0 +1,404 @@
+// METRICS
+//
+// numbers describing a generated level, to tune the generators with instead
+// of eyeballing the levels
+// distances are walking distances in tiles, doors (secret ones included)
+// count as walkable as they can all be opened eventually
+// the room graph is the one of Room::connections, so corridors that only
+// touch by accident do not count
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+};
+
+use super::corridor::anchor;
+use crate::game::{
+    door::{lock_of, DOOR},
+    level::Level,
+};
+
+#[derive(Clone, Debug)]
+pub struct LevelMetrics {
+    pub walkable_tiles: usize,
+    // walkable tiles that can be reached from the start room, relative to
+    // all walkable tiles
+    pub reachable_ratio: f32,
+    // rooms connected to a single other room
+    pub dead_ends: usize,
+    // independent cycles of the room graph
+    pub cycles: usize,
+    // None if the goal room cannot be reached
+    pub start_goal_distance: Option<usize>,
+    // (width, height) of every room, walls included
+    pub room_sizes: Vec<(usize, usize)>,
+}
+
+// doors count as walkable, even locked and secret ones
+pub fn is_walkable(level: &Level, idx: usize) -> bool {
+    let tile = &level.tiles[idx];
+    !tile.solid
+        || tile.ch == DOOR
+        || lock_of(tile.ch).is_some()
+        || level.secret_doors.contains(&idx)
+}
+
+// walks from all start tiles at once over the tiles that are walkable,
+// returns for every tile the index (into starts) of the start it is closest
+// to and the distance to it, None for tiles that cannot be reached
+pub fn flood_fill(
+    level: &Level,
+    starts: &[usize],
+    walkable: impl Fn(usize) -> bool,
+) -> Vec<Option<(usize, usize)>> {
+    let mut reached = vec![None; level.tiles.len()];
+    let mut queue = VecDeque::new();
+    for (i, &start) in starts.iter().enumerate() {
+        if reached[start].is_none() {
+            reached[start] = Some((i, 0));
+            queue.push_back(start);
+        }
+    }
+    while let Some(idx) = queue.pop_front() {
+        let neighbours = [
+            level.tile_index_above(idx),
+            level.tile_index_below(idx),
+            level.tile_index_left(idx),
+            level.tile_index_right(idx),
+        ];
+        for n in neighbours.into_iter().flatten() {
+            if reached[n].is_none() && walkable(n) {
+                reached[n] = reached[idx].map(|(start, dist)| (start, dist + 1));
+                queue.push_back(n);
+            }
+        }
+    }
+    reached
+}
+
+// walking distance from the tile to every tile, None for tiles that cannot
+// be reached
+pub fn tile_distances(level: &Level, from: (usize, usize)) -> Vec<Option<usize>> {
+    let start = from.1 * level.width + from.0;
+    flood_fill(level, &[start], |idx| is_walkable(level, idx))
+        .into_iter()
+        .map(|reached| reached.map(|(_, dist)| dist))
+        .collect()
+}
+
+// rooms directly connected to the room, without the room itself, sorted
+pub fn neighbour_rooms(level: &Level, room: usize) -> Vec<usize> {
+    let mut rooms: Vec<usize> = level.rooms[room]
+        .connections
+        .keys()
+        .copied()
+        .filter(|&other| other != room)
+        .collect();
+    rooms.sort();
+    rooms
+}
+
+pub fn dead_end_rooms(level: &Level) -> Vec<usize> {
+    (0..level.rooms.len())
+        .filter(|&room| neighbour_rooms(level, room).len() == 1)
+        .collect()
+}
+
+// edges - vertices + connected components
+fn cycle_count(level: &Level) -> usize {
+    let rooms = level.rooms.len();
+    let edges: usize = (0..rooms)
+        .map(|room| neighbour_rooms(level, room).len())
+        .sum::<usize>()
+        / 2;
+    let mut component = vec![None; rooms];
+    let mut components = 0;
+    for start in 0..rooms {
+        if component[start].is_some() {
+            continue;
+        }
+        component[start] = Some(components);
+        let mut queue = VecDeque::from([start]);
+        while let Some(room) = queue.pop_front() {
+            for other in neighbour_rooms(level, room) {
+                if component[other].is_none() {
+                    component[other] = Some(components);
+                    queue.push_back(other);
+                }
+            }
+        }
+        components += 1;
+    }
+    (edges + components).saturating_sub(rooms)
+}
+
+// start and goal are the rooms marked on the level, or the first and the
+// last room if there are none
+pub fn measure(level: &Level) -> LevelMetrics {
+    let start_room = level.start_room.unwrap_or(0);
+    let goal_room = level
+        .goal_room
+        .unwrap_or(level.rooms.len().saturating_sub(1));
+    let distances = tile_distances(level, anchor(level, start_room));
+    let walkable_tiles = (0..level.tiles.len())
+        .filter(|&idx| is_walkable(level, idx))
+        .count();
+    let reachable = distances.iter().filter(|dist| dist.is_some()).count();
+    let (x, y) = anchor(level, goal_room);
+
+    LevelMetrics {
+        walkable_tiles,
+        reachable_ratio: match walkable_tiles {
+            0 => 0.,
+            _ => reachable as f32 / walkable_tiles as f32,
+        },
+        dead_ends: dead_end_rooms(level).len(),
+        cycles: cycle_count(level),
+        start_goal_distance: distances[y * level.width + x],
+        room_sizes: level.rooms.iter().map(|room| (room.w, room.h)).collect(),
+    }
+}
+
+impl fmt::Display for LevelMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "reachable: {:.1}% of {} walkable tiles",
+            self.reachable_ratio * 100.,
+            self.walkable_tiles
+        )?;
+        writeln!(f, "dead ends: {}", self.dead_ends)?;
+        writeln!(f, "cycles: {}", self.cycles)?;
+        match self.start_goal_distance {
+            Some(dist) => writeln!(f, "start to goal: {} tiles", dist)?,
+            None => writeln!(f, "start to goal: unreachable")?,
+        }
+        let sizes: Vec<String> = self
+            .room_sizes
+            .iter()
+            .map(|(w, h)| format!("{}x{}", w, h))
+            .collect();
+        writeln!(f, "rooms: {}", sizes.join(" "))
+    }
+}
+
+// mean, min and max of a number over many levels
+#[derive(Clone, Debug, Default)]
+pub struct Stat {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Stat {
+    pub fn add(&mut self, value: f64) {
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    pub fn mean(&self) -> f64 {
+        match self.count {
+            0 => 0.,
+            _ => self.sum / self.count as f64,
+        }
+    }
+}
+
+impl fmt::Display for Stat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.2}, min {:.2}, max {:.2}",
+            self.mean(),
+            self.min,
+            self.max
+        )
+    }
+}
+
+// aggregate of the metrics of many levels
+#[derive(Clone, Debug, Default)]
+pub struct MetricsSummary {
+    pub levels: usize,
+    // levels that could not be generated at all
+    pub failed: usize,
+    pub reachable_ratio: Stat,
+    pub dead_ends: Stat,
+    pub cycles: Stat,
+    pub start_goal_distance: Stat,
+    // levels where the goal could not be reached
+    pub unreachable_goals: usize,
+    pub rooms: Stat,
+    // number of rooms with each width and height
+    pub room_widths: BTreeMap<usize, usize>,
+    pub room_heights: BTreeMap<usize, usize>,
+}
+
+impl MetricsSummary {
+    pub fn add(&mut self, metrics: &LevelMetrics) {
+        self.levels += 1;
+        self.reachable_ratio.add(metrics.reachable_ratio as f64);
+        self.dead_ends.add(metrics.dead_ends as f64);
+        self.cycles.add(metrics.cycles as f64);
+        match metrics.start_goal_distance {
+            Some(dist) => self.start_goal_distance.add(dist as f64),
+            None => self.unreachable_goals += 1,
+        }
+        self.rooms.add(metrics.room_sizes.len() as f64);
+        for (w, h) in &metrics.room_sizes {
+            *self.room_widths.entry(*w).or_default() += 1;
+            *self.room_heights.entry(*h).or_default() += 1;
+        }
+    }
+
+    pub fn add_failure(&mut self) {
+        self.levels += 1;
+        self.failed += 1;
+    }
+}
+
+fn write_distribution(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    counts: &BTreeMap<usize, usize>,
+) -> fmt::Result {
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(size, count)| format!("{}: {}", size, count))
+        .collect();
+    writeln!(f, "{}: {}", name, counts.join(", "))
+}
+
+impl fmt::Display for MetricsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "levels: {} ({} failed)", self.levels, self.failed)?;
+        writeln!(f, "reachable ratio: {}", self.reachable_ratio)?;
+        writeln!(f, "dead ends: {}", self.dead_ends)?;
+        writeln!(f, "cycles: {}", self.cycles)?;
+        writeln!(
+            f,
+            "start to goal: {} ({} unreachable)",
+            self.start_goal_distance, self.unreachable_goals
+        )?;
+        writeln!(f, "rooms: {}", self.rooms)?;
+        write_distribution(f, "room widths", &self.room_widths)?;
+        write_distribution(f, "room heights", &self.room_heights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room,
+        level_generator::{empty_level, set_rooms_connected},
+        resources::tests::level_from_rows,
+    };
+
+    // two rooms joined by a door and one that nothing leads to
+    const ROWS: [&str; 5] = [
+        "aaaaaaaaaaaaaaa",
+        "acccacccaaaccca",
+        "acccgcccaaaccca",
+        "acccacccaaaccca",
+        "aaaaaaaaaaaaaaa",
+    ];
+
+    fn level() -> Level {
+        let rooms = [0, 4, 10].map(|x| Room::new(x, 0, 5, 5));
+        let mut level = level_from_rows(&ROWS, rooms.to_vec());
+        set_rooms_connected(&mut level, 0, 1);
+        level
+    }
+
+    // rooms without tiles, connected as given
+    fn room_graph(rooms: usize, edges: &[(usize, usize)]) -> Level {
+        let mut level = empty_level(10, 10);
+        for _ in 0..rooms {
+            level.rooms.push(Room::new(0, 0, 5, 5));
+        }
+        for &(i, j) in edges {
+            set_rooms_connected(&mut level, i, j);
+        }
+        level
+    }
+
+    #[test]
+    fn distances_go_through_doors_but_not_walls() {
+        let level = level();
+        let distances = tile_distances(&level, (2, 2));
+        let at = |x: usize, y: usize| distances[y * level.width + x];
+        assert_eq!(at(2, 2), Some(0));
+        assert_eq!(at(4, 2), Some(2));
+        assert_eq!(at(7, 3), Some(6));
+        assert_eq!(at(0, 0), None);
+        assert_eq!(at(12, 2), None);
+    }
+
+    #[test]
+    fn flood_fill_finds_the_closest_start() {
+        let level = level();
+        let at = |x: usize, y: usize| y * level.width + x;
+        let reached = flood_fill(&level, &[at(2, 2), at(12, 2)], |idx| {
+            is_walkable(&level, idx)
+        });
+        assert_eq!(reached[at(7, 2)], Some((0, 5)));
+        assert_eq!(reached[at(12, 3)], Some((1, 1)));
+        assert_eq!(reached[at(8, 2)], None);
+
+        // without doors the first room is on its own
+        let reached = flood_fill(&level, &[at(2, 2)], |idx| !level.tiles[idx].solid);
+        assert_eq!(reached[at(4, 2)], None);
+    }
+
+    #[test]
+    fn unreachable_tiles_and_goals_are_measured() {
+        let mut level = level();
+        level.start_room = Some(0);
+        level.goal_room = Some(1);
+        let metrics = measure(&level);
+        // three rooms of 3x3 floor and the door
+        assert_eq!(metrics.walkable_tiles, 28);
+        assert_eq!(metrics.reachable_ratio, 19. / 28.);
+        assert_eq!(metrics.start_goal_distance, Some(4));
+        assert_eq!(metrics.room_sizes, vec![(5, 5); 3]);
+
+        level.goal_room = Some(2);
+        assert_eq!(measure(&level).start_goal_distance, None);
+    }
+
+    #[test]
+    fn dead_ends_and_cycles_come_from_the_room_graph() {
+        // a ring of four rooms
+        let ring = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        let level = room_graph(4, &ring);
+        assert_eq!(dead_end_rooms(&level), Vec::<usize>::new());
+        assert_eq!(cycle_count(&level), 1);
+
+        // with a room hanging off the ring and a separate pair of rooms
+        let level = room_graph(7, &[&ring[..], &[(0, 4), (5, 6)]].concat());
+        assert_eq!(neighbour_rooms(&level, 0), vec![1, 3, 4]);
+        assert_eq!(dead_end_rooms(&level), vec![4, 5, 6]);
+        assert_eq!(cycle_count(&level), 1);
+    }
+
+    #[test]
+    fn stats_track_mean_min_and_max() {
+        let mut stat = Stat::default();
+        assert_eq!(stat.mean(), 0.);
+        for value in [3., 1., 2.] {
+            stat.add(value);
+        }
+        assert_eq!(stat.count, 3);
+        assert_eq!(stat.mean(), 2.);
+        assert_eq!(stat.min, 1.);
+        assert_eq!(stat.max, 3.);
+    }
+}
@@ -0,0 +1,859 @@
+// LEVEL GENERATOR
+//
+//
+// Start Area ->   room  --> room --> room -->    -> End Area (with nexus in it?) ?
+//
+//
+//
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+use super::{
+    error::GenerateError,
+    level::{FogLevel, Level, Room, Tile},
+    resources::{BiomeDefinition, PrefabDefinition, TileDefinition},
+};
+
+use self::{
+    bsp::BspGenerator,
+    cave::CaveGenerator,
+    decoration::DEFAULT_DECORATION_DENSITY,
+    prefab::{add_prefab, random_prefab},
+    roles::DEFAULT_SECRET_CHANCE,
+    wfc::WfcGenerator,
+};
+
+pub mod biome;
+pub mod bsp;
+pub mod cave;
+mod corridor;
+pub mod decoration;
+pub mod dungeon;
+pub mod endpoints;
+pub mod locks;
+pub mod metrics;
+pub mod population;
+pub mod prefab;
+pub mod roles;
+pub mod wfc;
+
+pub const DEFAULT_ROOM_MIN_WIDTH: usize = 5;
+pub const DEFAULT_ROOM_MIN_HEIGHT: usize = 5;
+pub const DEFAULT_ROOM_MAX_WIDTH: usize = 15;
+pub const DEFAULT_ROOM_MAX_HEIGHT: usize = 15;
+// the walls of a room and one floor tile between them
+const ROOM_MIN_SIZE: usize = 3;
+
+// placeholder tiles generators build levels from, the biome pass paints them
+// with the tiles of the biome
+const GROUND: char = 'c';
+const WALL: char = 'a';
+const CORRIDOR_WALL: char = 'b';
+
+// how often a generator may try to place a room before giving up
+pub const DEFAULT_MAX_ATTEMPTS: usize = 1000;
+// extra corridors per spanning tree corridor, these create loops
+pub const DEFAULT_LOOP_RATIO: f32 = 0.2;
+pub const DEFAULT_PREFAB_CHANCE: f32 = 0.2;
+
+#[derive(Clone)]
+pub struct GeneratorParams {
+    // size in tiles
+    pub width: usize,
+    pub height: usize,
+    pub rooms: usize,
+    // size range of plain rooms in tiles, walls included, caves use the
+    // minimum for their chambers
+    pub room_min_width: usize,
+    pub room_min_height: usize,
+    pub room_max_width: usize,
+    pub room_max_height: usize,
+    // total room placement attempts, for generators that place by trial
+    pub max_attempts: usize,
+    // number of extra corridors, relative to the corridors needed to connect
+    // all rooms (rooms - 1), eg. 0.5 with 11 rooms adds 5 loops
+    pub loop_ratio: f32,
+    // in tiles, without the corridor walls
+    pub corridor_width: usize,
+    // hand made rooms, see prefab.rs
+    pub prefabs: Vec<PrefabDefinition>,
+    // chance of placing a prefab instead of a plain room
+    pub prefab_chance: f32,
+    // tiles and enemies to paint levels with, see biome.rs, levels keep the
+    // placeholder tiles without any
+    pub biomes: Vec<BiomeDefinition>,
+    // every room gets a random biome instead of all using the one of the
+    // level
+    pub biome_per_room: bool,
+    // chance of a room floor tile getting a decoration of its biome
+    pub decoration_density: f32,
+    // chance of a dead end becoming a secret room, see roles.rs
+    pub secret_chance: f32,
+    // tile rows of the example levels the wfc generator learns from
+    pub samples: Vec<Vec<String>>,
+    // definitions of all tiles, which of the tiles generators place are solid
+    pub tiles: HashMap<char, TileDefinition>,
+}
+
+impl GeneratorParams {
+    pub fn new(width: usize, height: usize, rooms: usize) -> Self {
+        Self {
+            width,
+            height,
+            rooms,
+            room_min_width: DEFAULT_ROOM_MIN_WIDTH,
+            room_min_height: DEFAULT_ROOM_MIN_HEIGHT,
+            room_max_width: DEFAULT_ROOM_MAX_WIDTH,
+            room_max_height: DEFAULT_ROOM_MAX_HEIGHT,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            loop_ratio: DEFAULT_LOOP_RATIO,
+            corridor_width: 1,
+            prefabs: vec![],
+            prefab_chance: DEFAULT_PREFAB_CHANCE,
+            biomes: vec![],
+            biome_per_room: false,
+            decoration_density: DEFAULT_DECORATION_DENSITY,
+            secret_chance: DEFAULT_SECRET_CHANCE,
+            samples: vec![],
+            tiles: HashMap::new(),
+        }
+    }
+
+    // upper bound of rooms that fit into the area, if all had minimum size
+    pub fn max_rooms(&self) -> usize {
+        (self.width / self.room_min_width) * (self.height / self.room_min_height)
+    }
+
+    // corridors need to fit through the doorway in a room wall
+    pub fn corridor_max_width(&self) -> usize {
+        self.room_min_width.min(self.room_min_height) - 2
+    }
+
+    // checks for parameters that no seed could ever generate a level for
+    pub fn validate(&self) -> Result<(), GenerateError> {
+        if self.rooms == 0 {
+            return Err(GenerateError::NoRooms);
+        }
+        for (min_name, min, max_name, max) in [
+            (
+                "room_min_width",
+                self.room_min_width,
+                "room_max_width",
+                self.room_max_width,
+            ),
+            (
+                "room_min_height",
+                self.room_min_height,
+                "room_max_height",
+                self.room_max_height,
+            ),
+        ] {
+            if min < ROOM_MIN_SIZE {
+                return Err(GenerateError::InvalidParam {
+                    name: min_name,
+                    value: min.to_string(),
+                    expected: format!("{} or more", ROOM_MIN_SIZE),
+                });
+            }
+            if max < min {
+                return Err(GenerateError::InvalidParam {
+                    name: max_name,
+                    value: max.to_string(),
+                    expected: format!("{} or more", min),
+                });
+            }
+        }
+        if self.width < self.room_min_width || self.height < self.room_min_height {
+            return Err(GenerateError::AreaTooSmall {
+                width: self.width,
+                height: self.height,
+                min_width: self.room_min_width,
+                min_height: self.room_min_height,
+            });
+        }
+        if self.rooms > self.max_rooms() {
+            return Err(GenerateError::TooManyRooms {
+                rooms: self.rooms,
+                max_rooms: self.max_rooms(),
+            });
+        }
+        if !(self.loop_ratio >= 0. && self.loop_ratio.is_finite()) {
+            return Err(GenerateError::InvalidParam {
+                name: "loop_ratio",
+                value: self.loop_ratio.to_string(),
+                expected: "a number of 0 or more".to_string(),
+            });
+        }
+        if !(0. ..=1.).contains(&self.decoration_density) {
+            return Err(GenerateError::InvalidParam {
+                name: "decoration_density",
+                value: self.decoration_density.to_string(),
+                expected: "0 to 1".to_string(),
+            });
+        }
+        if !(0. ..=1.).contains(&self.secret_chance) {
+            return Err(GenerateError::InvalidParam {
+                name: "secret_chance",
+                value: self.secret_chance.to_string(),
+                expected: "0 to 1".to_string(),
+            });
+        }
+        if !(0. ..=1.).contains(&self.prefab_chance) {
+            return Err(GenerateError::InvalidParam {
+                name: "prefab_chance",
+                value: self.prefab_chance.to_string(),
+                expected: "0 to 1".to_string(),
+            });
+        }
+        if self.corridor_width == 0 || self.corridor_width > self.corridor_max_width() {
+            return Err(GenerateError::InvalidParam {
+                name: "corridor_width",
+                value: self.corridor_width.to_string(),
+                expected: format!("1 to {}", self.corridor_max_width()),
+            });
+        }
+        // the placeholder tiles generators build levels from
+        for (ch, solid, expected) in [
+            (GROUND, false, "a walkable tile"),
+            (WALL, true, "a solid tile"),
+            (CORRIDOR_WALL, true, "a solid tile"),
+        ] {
+            if self.tiles.get(&ch).is_none_or(|def| def.solid != solid) {
+                return Err(GenerateError::InvalidParam {
+                    name: "tiles",
+                    value: ch.to_string(),
+                    expected: expected.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn add_random_room(level: &mut Level, params: &GeneratorParams, rng: &mut Rng) -> bool {
+    let room = Room::new(
+        rng.usize(0..level.width + 1),
+        rng.usize(0..level.height + 1),
+        rng.usize(params.room_min_width..params.room_max_width + 1),
+        rng.usize(params.room_min_height..params.room_max_height + 1),
+    );
+    add_room(level, room, &params.tiles)
+}
+
+// true if the area is inside the level and nothing was placed there yet
+fn is_area_free(level: &Level, x: usize, y: usize, w: usize, h: usize) -> bool {
+    if x + w > level.width || y + h > level.height {
+        return false;
+    }
+    for ty in y..y + h {
+        for tx in x..x + w {
+            if level.tiles[ty * level.width + tx].ch != ' ' {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+pub fn add_room(level: &mut Level, room: Room, tiles: &HashMap<char, TileDefinition>) -> bool {
+    if !is_area_free(level, room.x, room.y, room.w, room.h) {
+        return false;
+    }
+
+    for x in 0..room.w {
+        for y in 0..room.h {
+            let ch: char = if y == 0 || x == 0 || x == room.w - 1 || y == room.h - 1 {
+                WALL
+            } else {
+                GROUND
+            };
+            level.tiles[(room.y + y) * level.width + (room.x + x)] = Tile::from_char(ch, tiles);
+        }
+    }
+    level.rooms.push(room);
+    true
+}
+
+fn surround(
+    level: &mut Level,
+    x: usize,
+    y: usize,
+    ch: char,
+    tiles: &HashMap<char, TileDefinition>,
+) {
+    let xstart = if x > 0 { x - 1 } else { x };
+    let ystart = if y > 0 { y - 1 } else { y };
+    let xend = if x < level.width - 1 { x + 1 } else { x };
+    let yend = if y < level.height - 1 { y + 1 } else { y };
+
+    for x in xstart..xend + 1 {
+        for y in ystart..yend + 1 {
+            if level.tiles[y * level.width + x].ch == ' ' {
+                level.tiles[y * level.width + x] = Tile::from_char(ch, tiles);
+            }
+        }
+    }
+}
+
+// digs a corridor between two rooms, routed around all other rooms
+// returns false if there is no such route
+fn connect_rooms(
+    level: &mut Level,
+    i: usize,
+    j: usize,
+    width: usize,
+    ch: char,
+    ch_wall: char,
+    tiles: &HashMap<char, TileDefinition>,
+) -> bool {
+    if level.rooms[i].connections.keys().any(|&k| k == j) {
+        return true;
+    }
+    let start = corridor::anchor(level, i);
+    let end = corridor::anchor(level, j);
+
+    let owners = corridor::room_owners(level);
+    let Some(path) = corridor::find_path(level, &owners, (i, j), start, end, width) else {
+        return false;
+    };
+
+    for (x, y) in path {
+        for ty in y..y + width {
+            for tx in x..x + width {
+                // floor (of rooms and other corridors) stays as it is
+                if level.tiles[ty * level.width + tx].solid {
+                    level.tiles[ty * level.width + tx] = Tile::from_char(ch, tiles);
+                }
+                surround(level, tx, ty, ch_wall, tiles);
+            }
+        }
+    }
+
+    set_rooms_connected(level, i, j);
+    true
+}
+
+fn set_rooms_connected(level: &mut Level, i: usize, j: usize) {
+    level.rooms[i].connections.insert(j, true);
+    level.rooms[j].connections.insert(i, true);
+}
+
+// rows of tiles turned clockwise by 90 degrees
+fn rotated_grid(grid: &[Vec<char>]) -> Vec<Vec<char>> {
+    let (w, h) = (grid.first().map(|row| row.len()).unwrap_or(0), grid.len());
+    // the new row y is the old column y, read from the bottom up
+    (0..w)
+        .map(|x| (0..h).rev().map(|y| grid[y][x]).collect())
+        .collect()
+}
+
+// rows of tiles mirrored at the vertical axis
+fn mirrored_grid(grid: &[Vec<char>]) -> Vec<Vec<char>> {
+    grid.iter()
+        .map(|row| row.iter().rev().copied().collect())
+        .collect()
+}
+
+// edges of a minimum spanning tree over the room centers (prim)
+fn spanning_tree(rooms: &[Room]) -> Vec<(usize, usize)> {
+    let mut edges = vec![];
+    if rooms.is_empty() {
+        return edges;
+    }
+    let mut in_tree = vec![false; rooms.len()];
+    in_tree[0] = true;
+    for _ in 1..rooms.len() {
+        let mut best: Option<(usize, usize, f32)> = None;
+        for i in 0..rooms.len() {
+            if !in_tree[i] {
+                continue;
+            }
+            for j in 0..rooms.len() {
+                if in_tree[j] {
+                    continue;
+                }
+                let dist = rooms[i].center().distance(rooms[j].center());
+                if best.is_none_or(|(_, _, best_dist)| dist < best_dist) {
+                    best = Some((i, j, dist));
+                }
+            }
+        }
+        let (i, j, _) = best.unwrap();
+        in_tree[j] = true;
+        edges.push((i, j));
+    }
+    edges
+}
+
+// adds corridors between rooms that are not directly connected yet, shortest
+// first, until loop_ratio is reached or no more corridors can be routed
+fn add_loops(level: &mut Level, params: &GeneratorParams, rng: &mut Rng) {
+    let loops = ((level.rooms.len().saturating_sub(1)) as f32 * params.loop_ratio).round() as usize;
+
+    let mut candidates = vec![];
+    for i in 0..level.rooms.len() {
+        for j in i + 1..level.rooms.len() {
+            if !level.rooms[i].connections.contains_key(&j) {
+                candidates.push((i, j));
+            }
+        }
+    }
+    // shuffle first, so that equally long candidates are picked at random
+    rng.shuffle(&mut candidates);
+    candidates.sort_by(|&(a, b), &(c, d)| {
+        let ab = level.rooms[a].center().distance(level.rooms[b].center());
+        let cd = level.rooms[c].center().distance(level.rooms[d].center());
+        ab.total_cmp(&cd)
+    });
+
+    let mut added = 0;
+    for (i, j) in candidates {
+        if added == loops {
+            break;
+        }
+        if connect_rooms(
+            level,
+            i,
+            j,
+            params.corridor_width,
+            GROUND,
+            CORRIDOR_WALL,
+            &params.tiles,
+        ) {
+            added += 1;
+        }
+    }
+}
+
+// connects all rooms along a minimum spanning tree, then adds loops
+fn connect_room_graph(
+    level: &mut Level,
+    params: &GeneratorParams,
+    rng: &mut Rng,
+) -> Result<(), GenerateError> {
+    for i in 0..level.rooms.len() {
+        // every room is connected to itself
+        level.rooms[i].connections.insert(i, true);
+    }
+    for (i, j) in spanning_tree(&level.rooms) {
+        if !connect_rooms(
+            level,
+            i,
+            j,
+            params.corridor_width,
+            GROUND,
+            CORRIDOR_WALL,
+            &params.tiles,
+        ) {
+            return Err(GenerateError::Disconnected { room: j });
+        }
+    }
+    add_loops(level, params, rng);
+    Ok(())
+}
+
+// common interface of all level generators
+// the same rng state always results in the same level
+// generators validate the params first, errors other than those from
+// GeneratorParams::validate depend on the seed, so retrying with another one
+// can succeed
+pub trait LevelGenerator {
+    fn generate(&self, params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError>;
+}
+
+// names accepted by generator_by_name
+pub const GENERATOR_NAMES: [&str; 4] = ["random", "bsp", "cave", "wfc"];
+
+pub fn generator_by_name(name: &str) -> Option<Box<dyn LevelGenerator>> {
+    match name {
+        "random" => Some(Box::new(RandomRoomGenerator)),
+        "bsp" => Some(Box::new(BspGenerator)),
+        "cave" => Some(Box::new(CaveGenerator)),
+        "wfc" => Some(Box::new(WfcGenerator)),
+        _ => None,
+    }
+}
+
+// places rooms (or prefabs) at random positions, retrying on overlap, and
+// connects them along a spanning tree plus a few loops
+pub struct RandomRoomGenerator;
+
+impl LevelGenerator for RandomRoomGenerator {
+    fn generate(&self, params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError> {
+        params.validate()?;
+        // start at a random position?
+        // start in a random corner?
+
+        let mut level = empty_level(params.width, params.height);
+
+        let mut attempts = 0;
+        while level.rooms.len() < params.rooms {
+            if attempts == params.max_attempts {
+                return Err(GenerateError::AttemptsExhausted {
+                    placed: level.rooms.len(),
+                    rooms: params.rooms,
+                    attempts,
+                });
+            }
+            attempts += 1;
+            if !params.prefabs.is_empty() && rng.f32() < params.prefab_chance {
+                if let Some(def) = random_prefab(params, params.width, params.height, rng) {
+                    let x = rng.usize(0..params.width);
+                    let y = rng.usize(0..params.height);
+                    add_prefab(&mut level, &def, x, y, &params.tiles);
+                }
+            } else {
+                add_random_room(&mut level, params, rng);
+            }
+        }
+
+        connect_room_graph(&mut level, params, rng)?;
+
+        Ok(level)
+    }
+}
+
+// seeds tried by generate_with_retries before giving up
+pub const MAX_GENERATION_SEEDS: usize = 10;
+
+// seeds that generation failed for, with the reason
+pub type FailedSeeds = Vec<(u64, GenerateError)>;
+
+// generates a level, trying a new seed derived from the previous one when
+// generation fails for a seed
+// returns the level together with the seed it was generated from, the rng
+// state after generation and the seeds that failed before, it is up to the
+// caller to report those
+pub fn generate_with_retries(
+    generator: &dyn LevelGenerator,
+    params: &GeneratorParams,
+    seed: u64,
+) -> Result<(Level, u64, Rng, FailedSeeds), GenerateError> {
+    // no other seed can fix invalid params
+    params.validate()?;
+
+    let mut seed = seed;
+    let mut failed = vec![];
+    loop {
+        let mut rng = Rng::with_seed(seed);
+        match generator.generate(params, &mut rng) {
+            Ok(level) => return Ok((level, seed, rng, failed)),
+            Err(err) if failed.len() + 1 < MAX_GENERATION_SEEDS => {
+                failed.push((seed, err));
+                seed = Rng::with_seed(seed).u64(..);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+pub fn generate_level(params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError> {
+    RandomRoomGenerator.generate(params, rng)
+}
+
+fn empty_level(width: usize, height: usize) -> Level {
+    let mut level = Level {
+        width,
+        height,
+        tiles: vec![],
+        rooms: vec![],
+        start_room: None,
+        goal_room: None,
+        keys: vec![],
+        secret_doors: vec![],
+    };
+
+    // fill empty level, nothing is placed yet and nothing can be walked on
+    for _ in 0..width * height {
+        level.tiles.push(Tile {
+            fog: FogLevel::Opaque,
+            solid: true,
+            ch: ' ',
+        })
+    }
+
+    level
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::game::resources::{
+        parse_biome_definitions, parse_json, parse_prefab_definitions, tests::resources,
+        LevelDefinition, Resources, BIOMES_FILE, PREFABS_INDEX_FILE, SAMPLE_LEVEL_FILES,
+    };
+
+    fn read(file: &str) -> String {
+        std::fs::read_to_string(file).unwrap()
+    }
+
+    // params with the tiles of the game, without prefabs, biomes or samples
+    pub(crate) fn params_with_tiles(width: usize, height: usize, rooms: usize) -> GeneratorParams {
+        let mut params = GeneratorParams::new(width, height, rooms);
+        params.tiles = resources().tile_defintions;
+        params
+    }
+
+    // the params the game generates its levels with
+    pub(crate) fn game_params(res: &Resources) -> GeneratorParams {
+        let mut params = GeneratorParams::new(40, 150, 20);
+        params.tiles = res.tile_defintions.clone();
+        params.prefabs =
+            parse_prefab_definitions(&read(PREFABS_INDEX_FILE), |path| Ok(read(path)), res)
+                .unwrap();
+        params.biomes = parse_biome_definitions(BIOMES_FILE, &read(BIOMES_FILE), res).unwrap();
+        for file in SAMPLE_LEVEL_FILES {
+            let def: LevelDefinition = parse_json(file, &read(file)).unwrap();
+            params.samples.push(def.tiles);
+        }
+        params
+    }
+
+    // fails for the first `failures` seeds it is called with
+    struct FlakyGenerator {
+        failures: usize,
+        calls: Cell<usize>,
+    }
+
+    impl LevelGenerator for FlakyGenerator {
+        fn generate(
+            &self,
+            params: &GeneratorParams,
+            rng: &mut Rng,
+        ) -> Result<Level, GenerateError> {
+            self.calls.set(self.calls.get() + 1);
+            if self.calls.get() <= self.failures {
+                return Err(GenerateError::NotEnoughSpace {
+                    placed: 0,
+                    rooms: params.rooms,
+                });
+            }
+            RandomRoomGenerator.generate(params, rng)
+        }
+    }
+
+    fn flaky(failures: usize) -> FlakyGenerator {
+        FlakyGenerator {
+            failures,
+            calls: Cell::new(0),
+        }
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let params = params_with_tiles(40, 40, 0);
+        assert_eq!(params.validate(), Err(GenerateError::NoRooms));
+
+        let params = params_with_tiles(3, 40, 1);
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::AreaTooSmall { .. })
+        ));
+
+        let params = params_with_tiles(40, 40, 65);
+        assert_eq!(
+            params.validate(),
+            Err(GenerateError::TooManyRooms {
+                rooms: 65,
+                max_rooms: 64
+            })
+        );
+
+        let mut params = params_with_tiles(40, 40, 4);
+        params.loop_ratio = f32::NAN;
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::InvalidParam {
+                name: "loop_ratio",
+                ..
+            })
+        ));
+
+        let mut params = params_with_tiles(40, 40, 4);
+        params.corridor_width = params.corridor_max_width() + 1;
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::InvalidParam {
+                name: "corridor_width",
+                ..
+            })
+        ));
+
+        let mut params = params_with_tiles(40, 40, 4);
+        params.room_max_height = params.room_min_height - 1;
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::InvalidParam {
+                name: "room_max_height",
+                ..
+            })
+        ));
+
+        // corridors would not fit into rooms this small
+        let mut params = params_with_tiles(40, 40, 4);
+        params.room_min_width = 3;
+        params.corridor_width = 2;
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::InvalidParam {
+                name: "corridor_width",
+                ..
+            })
+        ));
+
+        // without tile definitions nothing is known to be walkable
+        let params = GeneratorParams::new(40, 40, 4);
+        assert!(matches!(
+            params.validate(),
+            Err(GenerateError::InvalidParam { name: "tiles", .. })
+        ));
+    }
+
+    #[test]
+    fn all_generators_validate_their_params() {
+        let params = params_with_tiles(40, 40, 65);
+        for name in GENERATOR_NAMES {
+            let generator = generator_by_name(name).unwrap();
+            let result = generator.generate(&params, &mut Rng::with_seed(1));
+            assert!(
+                matches!(result, Err(GenerateError::TooManyRooms { .. })),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn random_generator_gives_up_after_max_attempts() {
+        // as many rooms as fit when packed perfectly, which random placement
+        // never manages
+        let mut params = params_with_tiles(20, 20, 16);
+        params.max_attempts = 200;
+        let result = RandomRoomGenerator.generate(&params, &mut Rng::with_seed(1));
+        assert!(matches!(
+            result,
+            Err(GenerateError::AttemptsExhausted { attempts: 200, .. })
+        ));
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_level() {
+        let mut params = game_params(&resources());
+        params.height = 60;
+        params.rooms = 8;
+        for name in GENERATOR_NAMES {
+            let generator = generator_by_name(name).unwrap();
+            let a = generator.generate(&params, &mut Rng::with_seed(5)).unwrap();
+            let b = generator.generate(&params, &mut Rng::with_seed(5)).unwrap();
+            assert_eq!(a.to_rows(), b.to_rows(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn retries_report_the_failed_seeds() {
+        let params = params_with_tiles(40, 60, 8);
+        let (level, seed, _, failed) = generate_with_retries(&flaky(2), &params, 7).unwrap();
+        assert_eq!(level.rooms.len(), 8);
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].0, 7);
+        assert_eq!(failed[1].0, Rng::with_seed(7).u64(..));
+        assert_eq!(seed, Rng::with_seed(failed[1].0).u64(..));
+    }
+
+    #[test]
+    fn retries_stop_after_max_seeds() {
+        let params = params_with_tiles(40, 60, 8);
+        let generator = flaky(usize::MAX);
+        let result = generate_with_retries(&generator, &params, 7);
+        assert!(matches!(result, Err(GenerateError::NotEnoughSpace { .. })));
+        assert_eq!(generator.calls.get(), MAX_GENERATION_SEEDS);
+    }
+
+    #[test]
+    fn invalid_params_are_not_retried() {
+        let params = params_with_tiles(40, 60, 0);
+        let generator = flaky(0);
+        let result = generate_with_retries(&generator, &params, 7);
+        assert_eq!(result.err(), Some(GenerateError::NoRooms));
+        assert_eq!(generator.calls.get(), 0);
+    }
+
+    // every room can be walked to from the first one
+    fn assert_connected(level: &Level) {
+        let distances = metrics::tile_distances(level, corridor::anchor(level, 0));
+        for room in 0..level.rooms.len() {
+            let (x, y) = corridor::anchor(level, room);
+            assert!(
+                distances[y * level.width + x].is_some(),
+                "room {} is not reachable",
+                room
+            );
+        }
+    }
+
+    #[test]
+    fn rooms_are_connected_by_corridors() {
+        for name in ["random", "bsp"] {
+            let generator = generator_by_name(name).unwrap();
+            let mut params = params_with_tiles(40, 80, 10);
+            for corridor_width in 1..=params.corridor_max_width() {
+                params.corridor_width = corridor_width;
+                for seed in 0..10 {
+                    if let Ok(level) = generator.generate(&params, &mut Rng::with_seed(seed)) {
+                        assert_connected(&level);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_keep_to_the_size_range() {
+        let mut params = params_with_tiles(60, 60, 8);
+        params.room_min_width = 6;
+        params.room_max_width = 8;
+        params.room_min_height = 7;
+        params.room_max_height = 7;
+        for name in ["random", "bsp"] {
+            let generator = generator_by_name(name).unwrap();
+            for seed in 0..5 {
+                let level = generator
+                    .generate(&params, &mut Rng::with_seed(seed))
+                    .unwrap();
+                for room in &level.rooms {
+                    assert!((6..=8).contains(&room.w) && room.h == 7, "{}", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn loops_are_added_on_top_of_the_spanning_tree() {
+        let mut params = params_with_tiles(60, 60, 12);
+        params.loop_ratio = 0.;
+        let tree = RandomRoomGenerator
+            .generate(&params, &mut Rng::with_seed(3))
+            .unwrap();
+        let edges = |level: &Level| -> usize {
+            level
+                .rooms
+                .iter()
+                .map(|room| room.connections.len() - 1)
+                .sum::<usize>()
+                / 2
+        };
+        assert_eq!(edges(&tree), 11);
+
+        params.loop_ratio = 0.5;
+        let looped = RandomRoomGenerator
+            .generate(&params, &mut Rng::with_seed(3))
+            .unwrap();
+        assert!(edges(&looped) > 11);
+        assert!(edges(&looped) <= 11 + 6);
+        assert_connected(&looped);
+    }
+}
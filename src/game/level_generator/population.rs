@@ -0,0 +1,376 @@
+// ENEMY POPULATION
+//
+// fills the rooms of a generated level with enemies
+// rooms get a difficulty budget by their role that is spent on random
+// enemies, each one costing what is defined in enemies.json
+// the boss room gets more, start, shop and secret rooms stay empty
+// chasing and stationary enemies are placed inside the room, patrolling ones
+// in the corridors closest to the room, lined up with the corridor direction
+// rooms built from prefabs get the enemies of the prefab instead, rooms
+// painted with a biome only get the enemies living there
+
+use std::collections::HashSet;
+
+use fastrand::Rng;
+use macroquad::math::{vec2, Vec2};
+
+use super::{
+    corridor::{anchor, room_owners},
+    metrics::{flood_fill, is_walkable},
+};
+use crate::game::{
+    enemy::EnemyStrategy,
+    entity::Entity,
+    level::{Level, RoomRole, TILE_SIZE},
+    resources::{
+        BiomeDefinition, EnemyDefinition, LevelDefinition, LevelEnemyDefinition,
+        LevelKeyDefinition, Point, Resources, TilePosition,
+    },
+};
+
+// difficulty points spent per room by default
+pub const DEFAULT_ROOM_BUDGET: usize = 6;
+// budget of the boss room, relative to the others
+const BOSS_BUDGET_FACTOR: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PopulationParams {
+    // the player starts here, the room stays empty
+    pub start_room: usize,
+    // the nexus is placed here
+    pub goal_room: usize,
+    pub room_budget: usize,
+    // false for levels that lead further down instead of having a nexus
+    pub nexus: bool,
+}
+
+impl PopulationParams {
+    pub fn new(start_room: usize, goal_room: usize) -> Self {
+        Self {
+            start_room,
+            goal_room,
+            room_budget: DEFAULT_ROOM_BUDGET,
+            nexus: true,
+        }
+    }
+}
+
+// for every walkable tile (doors included) the room that is closest to it
+// when walking, None for other tiles and tiles not reachable from any room
+fn closest_rooms(level: &Level, owners: &[Option<usize>]) -> Vec<Option<usize>> {
+    let starts: Vec<usize> = (0..level.tiles.len())
+        .filter(|&idx| owners[idx].is_some() && is_walkable(level, idx))
+        .collect();
+    flood_fill(level, &starts, |idx| is_walkable(level, idx))
+        .into_iter()
+        .map(|reached| reached.and_then(|(start, _)| owners[starts[start]]))
+        .collect()
+}
+
+// enemies are placed before any door is opened, so doors are in the way
+fn is_free(level: &Level, idx: Option<usize>) -> bool {
+    idx.is_some_and(|idx| !level.tiles[idx].solid)
+}
+
+// tiles an enemy with the given strategy may be placed on
+fn spots(
+    level: &Level,
+    owners: &[Option<usize>],
+    closest: &[Option<usize>],
+    room: usize,
+    strategy: &EnemyStrategy,
+) -> Vec<usize> {
+    (0..level.tiles.len())
+        .filter(|&idx| !level.tiles[idx].solid)
+        .filter(|&idx| match strategy {
+            EnemyStrategy::FollowPlayer | EnemyStrategy::NoMovement => owners[idx] == Some(room),
+            EnemyStrategy::HorizontalPatrol => {
+                owners[idx].is_none()
+                    && closest[idx] == Some(room)
+                    && is_free(level, level.tile_index_left(idx))
+                    && is_free(level, level.tile_index_right(idx))
+            }
+            EnemyStrategy::VerticalPatrol => {
+                owners[idx].is_none()
+                    && closest[idx] == Some(room)
+                    && is_free(level, level.tile_index_above(idx))
+                    && is_free(level, level.tile_index_below(idx))
+            }
+        })
+        .collect()
+}
+
+fn tile_center(level: &Level, idx: usize) -> Vec2 {
+    (level.pos_by_index(idx) + vec2(0.5, 0.5)) * TILE_SIZE
+}
+
+// marks all tiles overlapped by a box as occupied
+fn occupy(level: &Level, occupied: &mut HashSet<usize>, pos: Vec2, dim: Vec2) {
+    let min = ((pos - dim / 2.) / TILE_SIZE).floor();
+    let max = ((pos + dim / 2.) / TILE_SIZE).ceil();
+    for y in min.y.max(0.) as usize..max.y as usize {
+        for x in min.x.max(0.) as usize..max.x as usize {
+            occupied.insert(y * level.width + x);
+        }
+    }
+}
+
+// enemies for all rooms of the level, without the nexus
+pub fn populate(
+    level: &Level,
+    params: &PopulationParams,
+    biomes: &[BiomeDefinition],
+    res: &Resources,
+    occupied: &mut HashSet<usize>,
+    rng: &mut Rng,
+) -> Vec<LevelEnemyDefinition> {
+    // sorted, so that the same rng state results in the same enemies
+    let mut defs: Vec<&EnemyDefinition> = res
+        .enemy_definitions
+        .values()
+        .filter(|def| def.cost > 0)
+        .collect();
+    defs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let owners = room_owners(level);
+    let closest = closest_rooms(level, &owners);
+
+    let mut enemies = vec![];
+    for room in 0..level.rooms.len() {
+        if room == params.start_room || level.rooms[room].prefab.is_some() {
+            continue;
+        }
+        let biome = level.rooms[room]
+            .biome
+            .as_ref()
+            .and_then(|name| biomes.iter().find(|biome| biome.name == *name));
+        let mut budget = match level.rooms[room].role {
+            RoomRole::Start | RoomRole::Shop | RoomRole::Secret => continue,
+            RoomRole::Boss => params.room_budget * BOSS_BUDGET_FACTOR,
+            RoomRole::Normal | RoomRole::Treasure => params.room_budget,
+        };
+        loop {
+            let candidates: Vec<(&EnemyDefinition, Vec<usize>)> = defs
+                .iter()
+                .filter(|def| def.cost <= budget)
+                .filter(|def| biome.is_none_or(|biome| biome.enemies.contains(&def.name)))
+                .filter_map(|def| {
+                    // strategies are validated when loading the definitions
+                    let strategy = EnemyStrategy::from_name(&def.strategy)?;
+                    let free: Vec<usize> = spots(level, &owners, &closest, room, &strategy)
+                        .into_iter()
+                        .filter(|idx| !occupied.contains(idx))
+                        .collect();
+                    (!free.is_empty()).then_some((*def, free))
+                })
+                .collect();
+            if candidates.is_empty() {
+                break;
+            }
+            let (def, free) = &candidates[rng.usize(0..candidates.len())];
+            let idx = free[rng.usize(0..free.len())];
+            let pos = tile_center(level, idx);
+            occupy(level, occupied, pos, vec2(def.dim.x, def.dim.y));
+            budget -= def.cost;
+            enemies.push(LevelEnemyDefinition {
+                name: def.name.to_string(),
+                x: pos.x,
+                y: pos.y,
+            });
+        }
+    }
+    enemies
+}
+
+// complete definition of a generated level: its tiles and keys, the player
+// in the start room, the nexus (if any) in the goal room and enemies
+// everywhere else
+pub fn level_definition(
+    level: &Level,
+    params: &PopulationParams,
+    biomes: &[BiomeDefinition],
+    res: &Resources,
+    rng: &mut Rng,
+) -> LevelDefinition {
+    // prefabs can have something solid in their center
+    let (x, y) = anchor(level, params.start_room);
+    let start = tile_center(level, y * level.width + x);
+    let (x, y) = anchor(level, params.goal_room);
+    let goal = tile_center(level, y * level.width + x);
+
+    // whatever is in the goal room center (nexus or stairs) stays free
+    let mut occupied = HashSet::new();
+    let goal_dim = match res.enemy_definitions.get("Nexus") {
+        Some(nexus) if params.nexus => vec2(nexus.dim.x, nexus.dim.y),
+        _ => vec2(TILE_SIZE, TILE_SIZE),
+    };
+    occupy(level, &mut occupied, goal, goal_dim);
+
+    for key in &level.keys {
+        occupy(level, &mut occupied, key.pos, key.dim());
+    }
+
+    let mut enemies = vec![];
+    if params.nexus {
+        enemies.push(LevelEnemyDefinition {
+            name: "Nexus".to_string(),
+            x: goal.x,
+            y: goal.y,
+        });
+    }
+    for (i, room) in level.rooms.iter().enumerate() {
+        if i == params.start_room {
+            continue;
+        }
+        for enemy in &room.enemies {
+            let tile = level.tile_index_at(vec2(enemy.x, enemy.y));
+            if tile.is_some_and(|tile| occupied.insert(tile)) {
+                enemies.push(enemy.clone());
+            }
+        }
+    }
+    enemies.extend(populate(level, params, biomes, res, &mut occupied, rng));
+
+    LevelDefinition {
+        tiles: level.to_rows(),
+        player: Point {
+            x: start.x,
+            y: start.y,
+        },
+        enemies,
+        keys: level
+            .keys
+            .iter()
+            .map(|key| LevelKeyDefinition {
+                lock: key.lock,
+                x: key.pos.x,
+                y: key.pos.y,
+            })
+            .collect(),
+        secret_doors: level
+            .secret_doors
+            .iter()
+            .map(|&idx| TilePosition {
+                x: idx % level.width,
+                y: idx / level.width,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room,
+        resources::tests::{level_from_rows, resources},
+    };
+
+    // four rooms in a row joined by horizontal corridors, and one below the
+    // second room joined by a vertical corridor
+    const ROWS: [&str; 13] = [
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "acccaaaaacccaaaaacccaaaaaccca",
+        "accccccccccccccccccccccccccca",
+        "acccaaaaacccaaaaacccaaaaaccca",
+        "aaaaaaaaaacaaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaaacaaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaaacaaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaaacaaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaaacaaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaacccaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaacccaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaacccaaaaaaaaaaaaaaaaa",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+    ];
+
+    // only the second room gets enemies, the others are start, shop and
+    // secret rooms
+    fn level(role: RoomRole) -> Level {
+        let mut rooms: Vec<Room> = (0..4).map(|room| Room::new(room * 8, 0, 5, 5)).collect();
+        rooms.push(Room::new(8, 8, 5, 5));
+        let roles = [
+            RoomRole::Start,
+            role,
+            RoomRole::Shop,
+            RoomRole::Secret,
+            RoomRole::Secret,
+        ];
+        for (room, role) in rooms.iter_mut().zip(roles) {
+            room.role = role;
+        }
+        level_from_rows(&ROWS, rooms)
+    }
+
+    // enemies with their tile, placed for all seeds
+    fn placed(level: &Level, res: &Resources, seeds: u64) -> Vec<Vec<(String, usize)>> {
+        let params = PopulationParams::new(0, 1);
+        (0..seeds)
+            .map(|seed| {
+                populate(
+                    level,
+                    &params,
+                    &[],
+                    res,
+                    &mut HashSet::new(),
+                    &mut Rng::with_seed(seed),
+                )
+                .into_iter()
+                .map(|enemy| {
+                    let tile = level.tile_index_at(vec2(enemy.x, enemy.y)).unwrap();
+                    (enemy.name, tile)
+                })
+                .collect()
+            })
+            .collect()
+    }
+
+    fn cost(enemies: &[(String, usize)], res: &Resources) -> usize {
+        enemies
+            .iter()
+            .map(|(name, _)| res.enemy_definitions[name].cost)
+            .sum()
+    }
+
+    #[test]
+    fn rooms_spend_their_budget() {
+        let res = resources();
+        for enemies in placed(&level(RoomRole::Normal), &res, 10) {
+            let cost = cost(&enemies, &res);
+            assert!((1..=DEFAULT_ROOM_BUDGET).contains(&cost), "{}", cost);
+        }
+        let boss_budget = DEFAULT_ROOM_BUDGET * BOSS_BUDGET_FACTOR;
+        for enemies in placed(&level(RoomRole::Boss), &res, 10) {
+            let cost = cost(&enemies, &res);
+            assert!(
+                (DEFAULT_ROOM_BUDGET + 1..=boss_budget).contains(&cost),
+                "{}",
+                cost
+            );
+        }
+    }
+
+    #[test]
+    fn enemies_are_placed_by_their_strategy() {
+        let res = resources();
+        let level = level(RoomRole::Treasure);
+        let owners = room_owners(&level);
+        let at = |x: usize, y: usize| y * level.width + x;
+        let mut names = HashSet::new();
+        for enemies in placed(&level, &res, 20) {
+            for (name, tile) in enemies {
+                assert!(!level.tiles[tile].solid);
+                // nothing goes to the start, shop and secret rooms or the
+                // corridor tiles closest to them
+                match res.enemy_definitions[&name].strategy.as_str() {
+                    "followPlayer" => assert_eq!(owners[tile], Some(1)),
+                    "horizontalPatrol" => assert!([at(7, 2), at(13, 2), at(14, 2)].contains(&tile)),
+                    "verticalPatrol" => assert!([at(10, 5), at(10, 6)].contains(&tile)),
+                    strategy => panic!("unexpected {} ({})", name, strategy),
+                }
+                names.insert(name);
+            }
+        }
+        assert_eq!(names.len(), 3);
+    }
+}
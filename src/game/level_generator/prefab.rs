@@ -0,0 +1,266 @@
+// PREFAB ROOMS
+//
+// hand made rooms from resources/prefabs/ that generators place instead of a
+// plain room, turned by a random multiple of 90 degrees and mirrored at
+// random unless the prefab is fixed
+// the enemies of a prefab are kept on the Room, the population pass adds
+// them to the level instead of spending a budget there, and so are its key
+// spots, which the locks pass uses when a key goes to the room
+// items are left out, there is nothing in the game yet to place
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+use super::{is_area_free, mirrored_grid, rotated_grid, GeneratorParams};
+use crate::game::{
+    level::{Level, Room, Tile, TILE_SIZE},
+    resources::{LevelEnemyDefinition, Point, PrefabDefinition, TileDefinition},
+};
+
+// in tiles
+pub fn size(def: &PrefabDefinition) -> (usize, usize) {
+    let w = def
+        .tiles
+        .first()
+        .map(|row| row.chars().count())
+        .unwrap_or(0);
+    (w, def.tiles.len())
+}
+
+fn grid(def: &PrefabDefinition) -> Vec<Vec<char>> {
+    def.tiles.iter().map(|row| row.chars().collect()).collect()
+}
+
+fn rows(grid: Vec<Vec<char>>) -> Vec<String> {
+    grid.into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
+// the prefab turned like its tiles, enemies and keys included
+fn rotated(def: &PrefabDefinition) -> PrefabDefinition {
+    let (_, h) = size(def);
+    PrefabDefinition {
+        name: def.name.to_string(),
+        tiles: rows(rotated_grid(&grid(def))),
+        enemies: def
+            .enemies
+            .iter()
+            .map(|enemy| LevelEnemyDefinition {
+                name: enemy.name.to_string(),
+                x: h as f32 * TILE_SIZE - enemy.y,
+                y: enemy.x,
+            })
+            .collect(),
+        keys: def
+            .keys
+            .iter()
+            .map(|key| Point {
+                x: h as f32 * TILE_SIZE - key.y,
+                y: key.x,
+            })
+            .collect(),
+        fixed: def.fixed,
+    }
+}
+
+// the prefab mirrored like its tiles, enemies and keys included
+fn mirrored(def: &PrefabDefinition) -> PrefabDefinition {
+    let (w, _) = size(def);
+    PrefabDefinition {
+        name: def.name.to_string(),
+        tiles: rows(mirrored_grid(&grid(def))),
+        enemies: def
+            .enemies
+            .iter()
+            .map(|enemy| LevelEnemyDefinition {
+                name: enemy.name.to_string(),
+                x: w as f32 * TILE_SIZE - enemy.x,
+                y: enemy.y,
+            })
+            .collect(),
+        keys: def
+            .keys
+            .iter()
+            .map(|key| Point {
+                x: w as f32 * TILE_SIZE - key.x,
+                y: key.y,
+            })
+            .collect(),
+        fixed: def.fixed,
+    }
+}
+
+// a random prefab in a random orientation that fits into the given size
+pub fn random_prefab(
+    params: &GeneratorParams,
+    max_w: usize,
+    max_h: usize,
+    rng: &mut Rng,
+) -> Option<PrefabDefinition> {
+    if params.prefabs.is_empty() {
+        return None;
+    }
+    let mut def = params.prefabs[rng.usize(0..params.prefabs.len())].clone();
+    if !def.fixed {
+        for _ in 0..rng.usize(0..4) {
+            def = rotated(&def);
+        }
+        if rng.bool() {
+            def = mirrored(&def);
+        }
+    }
+    let (w, h) = size(&def);
+    (w <= max_w && h <= max_h).then_some(def)
+}
+
+// places the prefab with its top left corner at x, y, if the space is free
+pub fn add_prefab(
+    level: &mut Level,
+    def: &PrefabDefinition,
+    x: usize,
+    y: usize,
+    tiles: &HashMap<char, TileDefinition>,
+) -> bool {
+    let (w, h) = size(def);
+    if !is_area_free(level, x, y, w, h) {
+        return false;
+    }
+
+    for (dy, row) in def.tiles.iter().enumerate() {
+        for (dx, ch) in row.chars().enumerate() {
+            level.tiles[(y + dy) * level.width + (x + dx)] = Tile::from_char(ch, tiles);
+        }
+    }
+    let mut room = Room::new(x, y, w, h);
+    room.prefab = Some(def.name.to_string());
+    room.enemies = def
+        .enemies
+        .iter()
+        .map(|enemy| LevelEnemyDefinition {
+            name: enemy.name.to_string(),
+            x: enemy.x + x as f32 * TILE_SIZE,
+            y: enemy.y + y as f32 * TILE_SIZE,
+        })
+        .collect();
+    room.key_spots = def
+        .keys
+        .iter()
+        .map(|key| {
+            (
+                x + (key.x / TILE_SIZE) as usize,
+                y + (key.y / TILE_SIZE) as usize,
+            )
+        })
+        .collect();
+    level.rooms.push(room);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level_generator::{add_room, empty_level, tests::params_with_tiles},
+        resources::tests::resources,
+    };
+
+    // 4x3, with the enemy on the `d` tile and the key on the `c` tile
+    fn prefab() -> PrefabDefinition {
+        PrefabDefinition {
+            name: "test".to_string(),
+            tiles: vec!["aaaa".to_string(), "acda".to_string(), "aaaa".to_string()],
+            enemies: vec![LevelEnemyDefinition {
+                name: "Blob".to_string(),
+                x: 2.5 * TILE_SIZE,
+                y: 1.5 * TILE_SIZE,
+            }],
+            keys: vec![Point {
+                x: 1.5 * TILE_SIZE,
+                y: 1.5 * TILE_SIZE,
+            }],
+            fixed: false,
+        }
+    }
+
+    fn tile_at(def: &PrefabDefinition, x: f32, y: f32) -> char {
+        let (x, y) = ((x / TILE_SIZE) as usize, (y / TILE_SIZE) as usize);
+        def.tiles[y].chars().nth(x).unwrap()
+    }
+
+    // the tile the first enemy stands on
+    fn enemy_tile(def: &PrefabDefinition) -> char {
+        tile_at(def, def.enemies[0].x, def.enemies[0].y)
+    }
+
+    // the tile of the first key spot
+    fn key_tile(def: &PrefabDefinition) -> char {
+        tile_at(def, def.keys[0].x, def.keys[0].y)
+    }
+
+    #[test]
+    fn rotating_keeps_enemies_and_keys_on_their_tile() {
+        let mut def = prefab();
+        for _ in 0..4 {
+            def = rotated(&def);
+            assert_eq!(enemy_tile(&def), 'd');
+            assert_eq!(key_tile(&def), 'c');
+        }
+        assert_eq!(def.tiles, prefab().tiles);
+        assert_eq!(def.enemies[0].x, prefab().enemies[0].x);
+        assert_eq!(def.enemies[0].y, prefab().enemies[0].y);
+    }
+
+    #[test]
+    fn rotating_swaps_the_size() {
+        let def = rotated(&prefab());
+        assert_eq!(size(&def), (3, 4));
+        assert_eq!(def.tiles, vec!["aaa", "aca", "ada", "aaa"]);
+    }
+
+    #[test]
+    fn mirroring_keeps_enemies_and_keys_on_their_tile() {
+        let def = mirrored(&prefab());
+        assert_eq!(def.tiles[1], "adca");
+        assert_eq!(enemy_tile(&def), 'd');
+        assert_eq!(key_tile(&def), 'c');
+        let def = mirrored(&def);
+        assert_eq!(def.tiles, prefab().tiles);
+        assert_eq!(def.enemies[0].x, prefab().enemies[0].x);
+    }
+
+    #[test]
+    fn fixed_prefabs_are_placed_as_authored() {
+        let mut params = params_with_tiles(40, 40, 1);
+        params.prefabs = vec![PrefabDefinition {
+            fixed: true,
+            ..prefab()
+        }];
+        let mut rng = Rng::with_seed(1);
+        for _ in 0..20 {
+            let def = random_prefab(&params, 40, 40, &mut rng).unwrap();
+            assert_eq!(def.tiles, prefab().tiles);
+        }
+        // does not fit when it is not allowed to turn
+        assert!(random_prefab(&params, 3, 4, &mut rng).is_none());
+    }
+
+    #[test]
+    fn prefabs_are_only_placed_on_free_space() {
+        let tiles = resources().tile_defintions;
+        let mut level = empty_level(20, 20);
+        assert!(add_room(&mut level, Room::new(0, 0, 5, 5), &tiles));
+        assert!(!add_prefab(&mut level, &prefab(), 3, 3, &tiles));
+        assert!(add_prefab(&mut level, &prefab(), 5, 3, &tiles));
+
+        let room = &level.rooms[1];
+        assert_eq!(room.prefab.as_deref(), Some("test"));
+        // enemies are moved along with the prefab
+        assert_eq!(room.enemies[0].x, 7.5 * TILE_SIZE);
+        assert_eq!(room.enemies[0].y, 4.5 * TILE_SIZE);
+        assert_eq!(level.tiles[4 * 20 + 7].ch, 'd');
+        // and so are key spots, in tiles
+        assert_eq!(room.key_spots, vec![(6, 4)]);
+    }
+}
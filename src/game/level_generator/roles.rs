@@ -0,0 +1,132 @@
+// ROOM ROLES
+//
+// gives every room a role by its position in the room graph, the other
+// passes use the roles to decide what goes into a room
+// - start and boss: the start and the goal room
+// - treasure or secret: dead ends, keys are hidden in treasure rooms and
+//   secret rooms are closed off by a secret door
+// - shop: the room closest to the start that is neither of the above, so
+//   that there is a safe room on the way
+// - normal: everything else
+
+use fastrand::Rng;
+
+use super::{endpoints::room_distances, metrics::neighbour_rooms};
+use crate::game::level::{Level, RoomRole};
+
+// chance of a dead end becoming a secret instead of a treasure room
+pub const DEFAULT_SECRET_CHANCE: f32 = 0.3;
+
+pub fn assign_roles(level: &mut Level, secret_chance: f32, rng: &mut Rng) {
+    let start = level.start_room.unwrap_or(0);
+    let goal = level
+        .goal_room
+        .unwrap_or(level.rooms.len().saturating_sub(1));
+
+    for room in 0..level.rooms.len() {
+        let role = if room == start {
+            RoomRole::Start
+        } else if room == goal {
+            RoomRole::Boss
+        } else if neighbour_rooms(level, room).len() == 1 {
+            match rng.f32() < secret_chance {
+                true => RoomRole::Secret,
+                false => RoomRole::Treasure,
+            }
+        } else {
+            RoomRole::Normal
+        };
+        level.rooms[room].role = role;
+    }
+
+    let distances = room_distances(level);
+    let shop = (0..level.rooms.len())
+        .filter(|&room| level.rooms[room].role == RoomRole::Normal)
+        .filter_map(|room| distances[start][room].map(|dist| (dist, room)))
+        .min();
+    if let Some((_, room)) = shop {
+        level.rooms[room].role = RoomRole::Shop;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        level::Room, level_generator::set_rooms_connected, resources::tests::level_from_rows,
+    };
+
+    // four rooms in a row, with a dead end below each of the middle two
+    const ROWS: [&str; 9] = [
+        "aaaaaaaaaaaaaaaaa",
+        "acccacccacccaccca",
+        "acccgcccgcccgccca",
+        "acccacccacccaccca",
+        "aaaaaagaaagaaaaaa",
+        "aaaaacccacccaaaaa",
+        "aaaaacccacccaaaaa",
+        "aaaaacccacccaaaaa",
+        "aaaaaaaaaaaaaaaaa",
+    ];
+
+    fn level(start: usize, goal: usize) -> Level {
+        let mut rooms: Vec<Room> = (0..4).map(|room| Room::new(room * 4, 0, 5, 5)).collect();
+        rooms.push(Room::new(4, 4, 5, 5));
+        rooms.push(Room::new(8, 4, 5, 5));
+        let mut level = level_from_rows(&ROWS, rooms);
+        for (i, j) in [(0, 1), (1, 2), (2, 3), (1, 4), (2, 5)] {
+            set_rooms_connected(&mut level, i, j);
+        }
+        level.start_room = Some(start);
+        level.goal_room = Some(goal);
+        level
+    }
+
+    fn roles(level: &Level) -> Vec<RoomRole> {
+        level.rooms.iter().map(|room| room.role).collect()
+    }
+
+    #[test]
+    fn rooms_get_their_roles_from_the_room_graph() {
+        let mut level = level(3, 0);
+        assign_roles(&mut level, 0., &mut Rng::with_seed(1));
+        // room 2 is the normal room closest to the start
+        assert_eq!(
+            roles(&level),
+            vec![
+                RoomRole::Boss,
+                RoomRole::Normal,
+                RoomRole::Shop,
+                RoomRole::Start,
+                RoomRole::Treasure,
+                RoomRole::Treasure,
+            ]
+        );
+    }
+
+    #[test]
+    fn dead_ends_are_secret_by_chance() {
+        let mut level = level(0, 3);
+        assign_roles(&mut level, 1., &mut Rng::with_seed(1));
+        assert_eq!(level.rooms[4].role, RoomRole::Secret);
+        assert_eq!(level.rooms[5].role, RoomRole::Secret);
+    }
+
+    #[test]
+    fn no_shop_without_a_normal_room() {
+        // the middle rooms are start and goal, all others are dead ends
+        let mut level = level(1, 2);
+        assign_roles(&mut level, 0., &mut Rng::with_seed(1));
+        assert_eq!(
+            roles(&level),
+            vec![
+                RoomRole::Treasure,
+                RoomRole::Start,
+                RoomRole::Boss,
+                RoomRole::Treasure,
+                RoomRole::Treasure,
+                RoomRole::Treasure,
+            ]
+        );
+    }
+}
@@ -0,0 +1,606 @@
+// WAVE FUNCTION COLLAPSE LEVEL GENERATOR
+//
+// cuts all small squares of tiles (patterns) out of example levels, the hand
+// made levels by default, also turned and mirrored, counts how often each is
+// found and which patterns overlap with the same tiles, and fills a level of
+// the requested size with overlapping patterns following the same rules
+// every position starts with all patterns possible, then the position with
+// the fewest options left is collapsed to one of them at random, and the
+// neighbours lose the patterns that do not fit next to it anymore, until all
+// positions are decided or one has no options left (contradiction, start
+// over)
+// walkable regions big enough for a room are joined to the largest one with
+// tunnels, the others are filled up, and the largest open squares become the
+// rooms, neighbours where their floor touches, like in the cave generator
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+use super::{
+    cave::Grid, mirrored_grid, rotated_grid, set_rooms_connected, GeneratorParams, LevelGenerator,
+    GROUND,
+};
+use crate::game::{
+    error::GenerateError,
+    level::{Level, Tile},
+    resources::TileDefinition,
+};
+
+// tries before giving up on contradictions, badly connected levels and levels
+// without space for all rooms
+const MAX_TRIES: usize = 20;
+// part of the level that has to be walkable once the regions are joined, the
+// sample levels are a bit less than half walkable
+const MIN_WALKABLE_SHARE: f32 = 0.3;
+// width and height of the patterns cut out of the samples
+const PATTERN_SIZE: usize = 2;
+
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+// index of the direction pointing the other way
+fn opposite(dir: usize) -> usize {
+    dir ^ 1
+}
+
+pub struct WfcGenerator;
+
+struct Pattern {
+    // rows of PATTERN_SIZE tiles
+    tiles: Vec<char>,
+    // how often the pattern is found in the samples
+    weight: usize,
+    // whether the pattern is found at the sample border in each direction,
+    // only those may be used at the level border, so levels stay closed
+    border: [bool; 4],
+}
+
+// what was learned from the samples
+struct Rules {
+    patterns: Vec<Pattern>,
+    // number of the tiles every pattern overlaps its neighbour with, for each
+    // direction
+    overlaps: Vec<[usize; 4]>,
+    // bits of the patterns with the overlap, for each direction and overlap,
+    // in words of 64 patterns
+    by_overlap: [Vec<Vec<u64>>; 4],
+    // number of different overlaps
+    overlap_count: usize,
+    // most used solid tile, fills up unconnected regions
+    filler: char,
+}
+
+// tiles of the pattern that overlap with a pattern next to it in the given
+// direction
+fn overlap(tiles: &[char], (dx, dy): (i32, i32)) -> Vec<char> {
+    let n = PATTERN_SIZE as i32;
+    (0..n * n)
+        .filter(|i| {
+            let (x, y) = (i % n - dx, i / n - dy);
+            x >= 0 && y >= 0 && x < n && y < n
+        })
+        .map(|i| tiles[i as usize])
+        .collect()
+}
+
+impl Rules {
+    fn learn(
+        samples: &[Vec<String>],
+        definitions: &HashMap<char, TileDefinition>,
+    ) -> Result<Rules, GenerateError> {
+        // every sample is also used turned and mirrored, the tiles do not
+        // care about their direction
+        let mut grids = vec![];
+        for rows in samples {
+            let grid: Vec<Vec<char>> = rows.iter().map(|row| row.chars().collect()).collect();
+            // rows of different length are cut to the shortest one
+            let width = grid.iter().map(|row| row.len()).min().unwrap_or(0);
+            let mut grid: Vec<Vec<char>> =
+                grid.into_iter().map(|row| row[..width].to_vec()).collect();
+            for _ in 0..4 {
+                grids.push(mirrored_grid(&grid));
+                grid = rotated_grid(&grid);
+                grids.push(grid.clone());
+            }
+        }
+
+        let mut patterns: Vec<Pattern> = vec![];
+        let mut index: HashMap<Vec<char>, usize> = HashMap::new();
+        for grid in grids {
+            let (width, height) = (grid.first().map(|row| row.len()).unwrap_or(0), grid.len());
+            if width < PATTERN_SIZE || height < PATTERN_SIZE {
+                continue;
+            }
+            for y in 0..=height - PATTERN_SIZE {
+                for x in 0..=width - PATTERN_SIZE {
+                    let tiles: Vec<char> = (0..PATTERN_SIZE * PATTERN_SIZE)
+                        .map(|i| grid[y + i / PATTERN_SIZE][x + i % PATTERN_SIZE])
+                        .collect();
+                    // the holes (` `) in the samples are not tiles to build
+                    // levels with
+                    if tiles.iter().any(|ch| !definitions.contains_key(ch)) {
+                        continue;
+                    }
+                    let border = [
+                        x == width - PATTERN_SIZE,
+                        x == 0,
+                        y == height - PATTERN_SIZE,
+                        y == 0,
+                    ];
+                    let pattern = *index.entry(tiles.clone()).or_insert_with(|| {
+                        patterns.push(Pattern {
+                            tiles,
+                            weight: 0,
+                            border: [false; 4],
+                        });
+                        patterns.len() - 1
+                    });
+                    let pattern = &mut patterns[pattern];
+                    pattern.weight += 1;
+                    for (seen, at_border) in pattern.border.iter_mut().zip(border) {
+                        *seen |= at_border;
+                    }
+                }
+            }
+        }
+        if patterns.is_empty() {
+            return Err(GenerateError::InvalidParam {
+                name: "samples",
+                value: "no patterns".to_string(),
+                expected: format!(
+                    "at least one sample level of {0}x{0} tiles or more",
+                    PATTERN_SIZE
+                ),
+            });
+        }
+
+        // patterns fit next to each other when they overlap with the same
+        // tiles, so a pattern fits next to the patterns with its overlap in
+        // the opposite direction
+        let mut numbers: HashMap<Vec<char>, usize> = HashMap::new();
+        let mut overlaps = vec![[0; 4]; patterns.len()];
+        for (i, pattern) in patterns.iter().enumerate() {
+            for (dir, &offset) in DIRECTIONS.iter().enumerate() {
+                let next = numbers.len();
+                overlaps[i][dir] = *numbers
+                    .entry(overlap(&pattern.tiles, offset))
+                    .or_insert(next);
+            }
+        }
+        let overlap_count = numbers.len();
+        let words = patterns.len().div_ceil(64);
+        let mut by_overlap: [Vec<Vec<u64>>; 4] =
+            std::array::from_fn(|_| vec![vec![0; words]; overlap_count]);
+        for (i, pattern_overlaps) in overlaps.iter().enumerate() {
+            for (dir, &overlap) in pattern_overlaps.iter().enumerate() {
+                by_overlap[dir][overlap][i / 64] |= 1 << (i % 64);
+            }
+        }
+
+        let mut counts: Vec<(char, usize)> = vec![];
+        for pattern in &patterns {
+            let ch = pattern.tiles[0];
+            match counts.iter_mut().find(|(other, _)| *other == ch) {
+                Some((_, count)) => *count += pattern.weight,
+                None => counts.push((ch, pattern.weight)),
+            }
+        }
+        let filler = counts
+            .into_iter()
+            .filter(|&(ch, _)| Tile::from_char(ch, definitions).solid)
+            .max_by_key(|&(_, count)| count)
+            .map(|(ch, _)| ch)
+            .unwrap_or(super::WALL);
+
+        Ok(Rules {
+            patterns,
+            overlaps,
+            by_overlap,
+            overlap_count,
+            filler,
+        })
+    }
+}
+
+// one pattern per position, the level tiles are covered by the patterns
+// with their top left corner at the position
+struct Wave<'a> {
+    rules: &'a Rules,
+    width: usize,
+    height: usize,
+    // bits of the patterns still possible, for each position
+    possible: Vec<u64>,
+    // words of possible per position
+    words: usize,
+    options: Vec<usize>,
+    // for each position, direction and overlap the number of patterns still
+    // possible at the position with that overlap towards the neighbour, the
+    // patterns of the neighbour with the same overlap fit while it is not 0
+    counts: Vec<u32>,
+    // removed patterns whose neighbours are not updated yet, as position,
+    // word and bits
+    removed: Vec<(usize, usize, u64)>,
+    // positions that lost options since they were last queued
+    changed: Vec<usize>,
+    // undecided positions, by their number of options
+    queue: Vec<Vec<usize>>,
+    // no position is queued with fewer options
+    fewest: usize,
+}
+
+impl<'a> Wave<'a> {
+    fn new(rules: &'a Rules, width: usize, height: usize) -> Self {
+        let patterns = rules.patterns.len();
+        let positions = width * height;
+        let words = patterns.div_ceil(64);
+        let mut possible = vec![0; words];
+        for pattern in 0..patterns {
+            possible[pattern / 64] |= 1 << (pattern % 64);
+        }
+        let mut counts = vec![0; DIRECTIONS.len() * rules.overlap_count];
+        for overlaps in &rules.overlaps {
+            for (dir, &overlap) in overlaps.iter().enumerate() {
+                counts[dir * rules.overlap_count + overlap] += 1;
+            }
+        }
+        let mut queue = vec![vec![]; patterns + 1];
+        queue[patterns] = (0..positions).collect();
+        Wave {
+            rules,
+            width,
+            height,
+            possible: possible.repeat(positions),
+            words,
+            options: vec![patterns; positions],
+            counts: counts.repeat(positions),
+            removed: vec![],
+            changed: vec![],
+            queue,
+            fewest: patterns,
+        }
+    }
+
+    fn is_possible(&self, idx: usize, pattern: usize) -> bool {
+        self.possible[idx * self.words + pattern / 64] & (1 << (pattern % 64)) != 0
+    }
+
+    // removes the patterns of the neighbours that have nothing left to fit
+    // next to, and so on, false on contradiction
+    fn propagate(&mut self) -> bool {
+        let rules = self.rules;
+        let stride = DIRECTIONS.len() * rules.overlap_count;
+        while let Some((idx, word, bits)) = self.removed.pop() {
+            let (x, y) = (idx % self.width, idx / self.width);
+            // in the order of DIRECTIONS
+            let neighbours = [
+                (x + 1 < self.width).then_some(idx + 1),
+                (x > 0).then(|| idx - 1),
+                (y + 1 < self.height).then_some(idx + self.width),
+                (y > 0).then(|| idx - self.width),
+            ];
+            for (dir, n) in neighbours.into_iter().enumerate() {
+                let Some(n) = n else {
+                    continue;
+                };
+                let counts = idx * stride + dir * rules.overlap_count;
+                let mut bits = bits;
+                while bits != 0 {
+                    let pattern = word * 64 + bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                    let overlap = rules.overlaps[pattern][dir];
+                    self.counts[counts + overlap] -= 1;
+                    if self.counts[counts + overlap] == 0
+                        && !self.ban_all(n, &rules.by_overlap[opposite(dir)][overlap])
+                    {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // bans the patterns whose bits are set, false on contradiction
+    fn ban_all(&mut self, idx: usize, patterns: &[u64]) -> bool {
+        let mut banned = false;
+        for (word, &bits) in patterns.iter().enumerate() {
+            let possible = &mut self.possible[idx * self.words + word];
+            let removed = *possible & bits;
+            if removed == 0 {
+                continue;
+            }
+            *possible &= !removed;
+            self.options[idx] -= removed.count_ones() as usize;
+            self.removed.push((idx, word, removed));
+            banned = true;
+        }
+        // bans come in runs at the same position
+        if banned && self.changed.last() != Some(&idx) {
+            self.changed.push(idx);
+        }
+        self.options[idx] > 0
+    }
+
+    // queues the positions that lost options again, with their new number
+    fn requeue(&mut self) {
+        for idx in self.changed.drain(..) {
+            let options = self.options[idx];
+            if options > 1 {
+                self.queue[options].push(idx);
+                self.fewest = self.fewest.min(options);
+            }
+        }
+    }
+
+    // the undecided position with the fewest options, random among equals
+    fn least_options(&mut self, rng: &mut Rng) -> Option<usize> {
+        while self.fewest < self.queue.len() {
+            let queued = &mut self.queue[self.fewest];
+            while !queued.is_empty() {
+                let idx = queued.swap_remove(rng.usize(0..queued.len()));
+                // still queued with a number of options it has lost since
+                if self.options[idx] == self.fewest {
+                    return Some(idx);
+                }
+            }
+            self.fewest += 1;
+        }
+        None
+    }
+
+    fn possible_patterns(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.rules.patterns.len()).filter(move |&pattern| self.is_possible(idx, pattern))
+    }
+
+    // decides the pattern of the position, weighted by how often the
+    // patterns are found in the samples
+    fn collapse(&mut self, idx: usize, rng: &mut Rng) -> bool {
+        let options: Vec<usize> = self.possible_patterns(idx).collect();
+        let total: usize = options
+            .iter()
+            .map(|&pattern| self.rules.patterns[pattern].weight)
+            .sum();
+        let mut pick = rng.usize(0..total.max(1));
+        let mut chosen = options[0];
+        for &pattern in &options {
+            let weight = self.rules.patterns[pattern].weight;
+            if pick < weight {
+                chosen = pattern;
+                break;
+            }
+            pick -= weight;
+        }
+        let mut others = vec![u64::MAX; self.words];
+        others[chosen / 64] &= !(1 << (chosen % 64));
+        self.ban_all(idx, &others)
+    }
+
+    // tiles of a level that is PATTERN_SIZE - 1 tiles wider and higher than
+    // the wave, None on contradiction
+    fn run(mut self, rng: &mut Rng) -> Option<Vec<char>> {
+        for idx in 0..self.options.len() {
+            let (x, y) = (idx % self.width, idx / self.width);
+            let at_border = [x == self.width - 1, x == 0, y == self.height - 1, y == 0];
+            if !at_border.contains(&true) {
+                continue;
+            }
+            let mut misfits = vec![0; self.words];
+            for (pattern, Pattern { border, .. }) in self.rules.patterns.iter().enumerate() {
+                if (0..DIRECTIONS.len()).any(|dir| at_border[dir] && !border[dir]) {
+                    misfits[pattern / 64] |= 1 << (pattern % 64);
+                }
+            }
+            if !self.ban_all(idx, &misfits) {
+                return None;
+            }
+        }
+        if !self.propagate() {
+            return None;
+        }
+        self.requeue();
+
+        while let Some(idx) = self.least_options(rng) {
+            if !self.collapse(idx, rng) || !self.propagate() {
+                return None;
+            }
+            self.requeue();
+        }
+
+        let width = self.width + PATTERN_SIZE - 1;
+        let height = self.height + PATTERN_SIZE - 1;
+        let mut chars = vec![super::WALL; width * height];
+        for idx in 0..self.options.len() {
+            let pattern = &self.rules.patterns[self.possible_patterns(idx).next()?];
+            let (x, y) = (idx % self.width, idx / self.width);
+            for (i, &ch) in pattern.tiles.iter().enumerate() {
+                chars[(y + i / PATTERN_SIZE) * width + x + i % PATTERN_SIZE] = ch;
+            }
+        }
+        Some(chars)
+    }
+}
+
+// number of tiles of every region
+fn region_sizes(regions: &[Option<usize>]) -> Vec<usize> {
+    let mut sizes = vec![];
+    for &region in regions.iter().flatten() {
+        if region >= sizes.len() {
+            sizes.resize(region + 1, 0);
+        }
+        sizes[region] += 1;
+    }
+    sizes
+}
+
+// digs a tunnel from every region big enough for a room to the closest tile
+// of the largest region, smaller regions are left to be filled up
+fn join_regions(grid: &mut Grid, params: &GeneratorParams) {
+    let min_size = params.room_min_width * params.room_min_height;
+    let regions = grid.regions();
+    let sizes = region_sizes(&regions);
+    let Some(main_region) = (0..sizes.len()).max_by_key(|&region| sizes[region]) else {
+        return;
+    };
+    let main_tiles: Vec<usize> = (0..regions.len())
+        .filter(|&idx| regions[idx] == Some(main_region))
+        .collect();
+    let width = grid.width;
+    for (region, &size) in sizes.iter().enumerate() {
+        if region == main_region || size < min_size {
+            continue;
+        }
+        // from the first tile of the region, the tunnel might cross other
+        // regions on the way, which joins them as well
+        let Some(from) = regions.iter().position(|&r| r == Some(region)) else {
+            continue;
+        };
+        let (fx, fy) = (from % width, from / width);
+        let to = main_tiles
+            .iter()
+            .map(|&idx| (idx % width, idx / width))
+            .min_by_key(|&(x, y)| x.abs_diff(fx) + y.abs_diff(fy));
+        if let Some(to) = to {
+            grid.dig((fx, fy), to);
+        }
+    }
+}
+
+impl LevelGenerator for WfcGenerator {
+    fn generate(&self, params: &GeneratorParams, rng: &mut Rng) -> Result<Level, GenerateError> {
+        params.validate()?;
+        let rules = Rules::learn(&params.samples, &params.tiles)?;
+        let (width, height) = (params.width, params.height);
+
+        for _ in 0..MAX_TRIES {
+            let wave = Wave::new(&rules, width + 1 - PATTERN_SIZE, height + 1 - PATTERN_SIZE);
+            let Some(mut chars) = wave.run(rng) else {
+                continue;
+            };
+
+            let mut grid = Grid {
+                width,
+                height,
+                walls: chars
+                    .iter()
+                    .map(|&ch| Tile::from_char(ch, &params.tiles).solid)
+                    .collect(),
+            };
+            let walls = grid.walls.clone();
+            join_regions(&mut grid, params);
+            for (idx, ch) in chars.iter_mut().enumerate() {
+                if walls[idx] && !grid.walls[idx] {
+                    *ch = GROUND;
+                }
+            }
+
+            // connectivity, only the largest walkable region is kept
+            let regions = grid.regions();
+            let sizes = region_sizes(&regions);
+            let Some((main_region, &main_size)) =
+                sizes.iter().enumerate().max_by_key(|(_, &size)| size)
+            else {
+                continue;
+            };
+            if (main_size as f32) < (width * height) as f32 * MIN_WALKABLE_SHARE {
+                continue;
+            }
+            for (idx, region) in regions.iter().enumerate() {
+                if region.is_some_and(|region| region != main_region) {
+                    chars[idx] = rules.filler;
+                    grid.walls[idx] = true;
+                }
+            }
+
+            let chambers = grid.chambers(params.rooms, params);
+            if chambers.len() < params.rooms {
+                continue;
+            }
+
+            let edges = grid.chamber_graph(&chambers);
+            let mut level = Level {
+                width,
+                height,
+                tiles: chars
+                    .into_iter()
+                    .map(|ch| Tile::from_char(ch, &params.tiles))
+                    .collect(),
+                rooms: chambers,
+                start_room: None,
+                goal_room: None,
+                keys: vec![],
+                secret_doors: vec![],
+            };
+            for i in 0..level.rooms.len() {
+                // every room is connected to itself
+                level.rooms[i].connections.insert(i, true);
+            }
+            for (i, j) in edges {
+                set_rooms_connected(&mut level, i, j);
+            }
+            return Ok(level);
+        }
+        Err(GenerateError::TooManyContradictions { tries: MAX_TRIES })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::game::{
+        level_generator::{metrics, tests::game_params},
+        resources::tests::resources,
+    };
+
+    #[test]
+    fn every_square_of_the_output_is_a_learned_pattern() {
+        let res = resources();
+        let params = game_params(&res);
+        let rules = Rules::learn(&params.samples, &params.tiles).unwrap();
+        let patterns: HashSet<&[char]> = rules.patterns.iter().map(|p| &p.tiles[..]).collect();
+
+        let (width, height) = (30, 20);
+        let mut rng = Rng::with_seed(1);
+        let chars = (0..MAX_TRIES)
+            .find_map(|_| {
+                Wave::new(&rules, width + 1 - PATTERN_SIZE, height + 1 - PATTERN_SIZE).run(&mut rng)
+            })
+            .unwrap();
+        for y in 0..=height - PATTERN_SIZE {
+            for x in 0..=width - PATTERN_SIZE {
+                let square: Vec<char> = (0..PATTERN_SIZE * PATTERN_SIZE)
+                    .map(|i| chars[(y + i / PATTERN_SIZE) * width + x + i % PATTERN_SIZE])
+                    .collect();
+                assert!(patterns.contains(&square[..]), "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn levels_are_walkable_and_rooms_big_enough() {
+        let res = resources();
+        let mut params = game_params(&res);
+        params.width = 40;
+        params.height = 40;
+        params.rooms = 6;
+        let level = WfcGenerator
+            .generate(&params, &mut Rng::with_seed(2))
+            .unwrap();
+
+        let walkable = level.tiles.iter().filter(|tile| !tile.solid).count();
+        assert!(walkable as f32 >= (40 * 40) as f32 * MIN_WALKABLE_SHARE);
+        let first = &level.rooms[0];
+        let distances = metrics::tile_distances(&level, (first.x, first.y));
+        for room in &level.rooms {
+            assert!(room.w >= params.room_min_width && room.h >= params.room_min_height);
+            for y in room.y..room.y + room.h {
+                for x in room.x..room.x + room.w {
+                    assert!(distances[y * level.width + x].is_some(), "at {}, {}", x, y);
+                }
+            }
+        }
+        // no holes are left over from the samples
+        assert!(level.tiles.iter().all(|tile| tile.ch != ' '));
+    }
+}
@@ -1,35 +1,66 @@
+use std::collections::HashMap;
+
 use macroquad::{
     math::{vec2, Rect, Vec2},
-    time::get_time,
+    time::get_frame_time,
     window::{screen_height, screen_width},
 };
 
 use self::{
     camera::GameCamera,
+    clock::GameClock,
     controls::Controls,
-    enemy::{create_enemy, Enemy},
-    level::{Level, World},
-    player::Player,
-    resources::Resources,
+    door::{create_keys, secret_door_tiles},
+    enemy::{count_nexus, create_enemy, Enemy},
+    error::ResourceError,
+    events::{Actor, GameEvent},
+    floor::Floor,
+    level::{Level, World, TILE_SIZE},
+    player::{create_player, Player},
+    projectile::{create_projectile, Projectile, ProjectileOwner},
+    resources::{LevelDefinition, Resources},
+    spatial::SpatialGrid,
 };
 
 pub mod camera;
+pub mod clock;
 pub mod controls;
+pub mod door;
 pub mod enemy;
 pub mod entity;
+pub mod error;
+pub mod events;
+pub mod floor;
 pub mod gfx;
 pub mod inventory;
 pub mod level;
 pub mod level_generator;
 pub mod player;
+pub mod projectile;
 pub mod resources;
+pub mod spatial;
+
+// duration of a single simulation tick in seconds
+pub const TICK: f32 = 1. / 60.;
+
+// frame times above this are clamped so that a long hitch does not result
+// in hundreds of ticks being simulated at once
+const MAX_FRAME_TIME: f32 = 0.25;
 
 pub enum GameState {
     MainMenu,
     InGame,
+    Paused,
     PostGame,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum LevelOutcome {
+    Won,
+    Lost,
+}
+
+#[derive(Clone)]
 pub struct GameStats {
     pub damage_dealt: usize,
     pub damage_received: usize,
@@ -37,57 +68,226 @@ pub struct GameStats {
     pub time_spent: f64,
 }
 
-pub struct Game<'a> {
+#[derive(Clone)]
+pub struct Game {
     pub lvl: Level,
-    pub player: Player<'a>,
-    pub enemies: Vec<Enemy<'a>>,
+    pub player: Player,
+    pub enemies: Vec<Enemy>,
+    // broadphase for collisions with enemies, holds indexes into enemies
+    pub enemy_grid: SpatialGrid,
+    pub projectiles: Vec<Projectile>,
     pub world: World,
     pub controls: Controls,
     pub camera: GameCamera,
     pub stats: GameStats,
+    // events emitted since the last drain_events
+    pub events: Vec<GameEvent>,
+    pub outcome: Option<LevelOutcome>,
+
+    // index of the current floor and number of floors of the run, the nexus
+    // is on the last one
+    pub floor: usize,
+    pub floors: usize,
+    // all floors visited before, except the current one
+    pub other_floors: HashMap<usize, Floor>,
+    // whether the player stands on stairs, they are only taken when stepped
+    // on, not when arriving on them
+    pub on_stairs: bool,
+
+    // id given to the next enemy added to the game
+    pub next_enemy_id: usize,
+
+    // seed of the run, the seeds of all floors are derived from it, shown to
+    // the player so that the run can be reproduced with --seed
+    pub seed: u64,
+
+    // advanced by TICK on every step
+    pub clock: GameClock,
+    // frame time not yet consumed by ticks
+    pub accumulator: f32,
+    // clicks that happened in frames where no tick was run
+    pub pending_controls: Controls,
 }
 
-impl<'a> Game<'a> {
-    pub fn add_enemy(&mut self, enemy: Enemy<'a>) {
+impl Game {
+    // creates a game for the given level, the tiles of lvl_def are ignored
+    // in favor of lvl
+    // viewport is the size of the screen area the game is drawn into
+    pub fn new(
+        lvl: Level,
+        lvl_def: LevelDefinition,
+        seed: u64,
+        viewport: Vec2,
+        res: &Resources,
+    ) -> Result<Self, ResourceError> {
+        let world = World {
+            dim: vec2(
+                (lvl.width as f32) * TILE_SIZE,
+                (lvl.height as f32) * TILE_SIZE,
+            ),
+        };
+
+        let player = create_player(vec2(lvl_def.player.x, lvl_def.player.y), res);
+
+        let cam_w = viewport.x.min(world.dim.x);
+        let cam_h = viewport.y.min(world.dim.y);
+        let camera = GameCamera {
+            dim: vec2(cam_w, cam_h),
+            pos: vec2(player.pos.x - cam_w / 2., player.pos.y - cam_h / 2.),
+        };
+
+        let mut game = Game {
+            lvl,
+            player,
+            enemies: vec![],
+            enemy_grid: SpatialGrid::default(),
+            projectiles: vec![],
+            world,
+            controls: Controls::default(),
+            camera,
+            stats: GameStats {
+                damage_dealt: 0,
+                damage_received: 0,
+                enemies_killed: 0,
+                time_spent: 0.,
+            },
+            events: vec![],
+            outcome: None,
+            floor: 0,
+            floors: 1,
+            other_floors: HashMap::new(),
+            on_stairs: false,
+            next_enemy_id: 0,
+            seed,
+            clock: GameClock::default(),
+            accumulator: 0.,
+            pending_controls: Controls::default(),
+        };
+
+        game.lvl.keys = create_keys(&lvl_def.keys);
+        game.lvl.secret_doors = secret_door_tiles(&game.lvl, &lvl_def.secret_doors);
+
+        for enemy in lvl_def.enemies {
+            game.add_enemy(create_enemy(enemy.name, vec2(enemy.x, enemy.y), res)?);
+        }
+
+        Ok(game)
+    }
+
+    pub fn add_enemy(&mut self, mut enemy: Enemy) {
+        enemy.id = self.next_enemy_id;
+        self.next_enemy_id += 1;
+        self.enemy_grid
+            .insert(self.enemies.len(), enemy.pos, enemy.dim);
         self.enemies.push(enemy);
     }
 
-    pub fn update(&mut self, res: &'a Resources) {
-        self.update_controls();
+    // advances the game using the current macroquad input and frame time
+    pub fn update(&mut self, res: &Resources) {
+        let controls = Controls::from_input(self.offset()).with_clicks_from(&self.pending_controls);
+        self.advance(get_frame_time().min(MAX_FRAME_TIME), controls, res);
+    }
+
+    // runs as many fixed ticks as fit into the elapsed (unscaled) time
+    // stops early when the player takes stairs, the caller has to switch the
+    // floor before the game goes on
+    pub fn advance(&mut self, frame_time: f32, controls: Controls, res: &Resources) {
+        let mut controls = controls;
+        self.accumulator += self.clock.scaled(frame_time);
+        while self.accumulator >= TICK {
+            self.step(controls, res);
+            self.accumulator -= TICK;
+            // clicks only apply to the first tick of a frame
+            controls = controls.without_clicks();
+            if self.floor_change_pending() {
+                // the rest of the frame is not played on the new floor either
+                self.accumulator = 0.;
+                break;
+            }
+        }
+        self.pending_controls = controls;
+    }
+
+    // advances the game by one tick of TICK seconds
+    // does not access any macroquad globals, so this can be used without a
+    // window (tests, tools)
+    pub fn step(&mut self, controls: Controls, res: &Resources) {
+        self.controls = controls;
+        let time = self.clock.now();
+        let dt = TICK;
 
-        self.update_enemies();
+        self.update_projectiles(dt);
+        self.update_enemies(time, dt);
 
         // TODO: meh .. put everything into update_player
-        let time = get_time();
         if self.controls.is_q && !self.player.q.is_on_cooldown(time) {
             // launch rocket
-            let mut rocket = create_enemy("Rocket".to_string(), self.player.pos, res);
-            rocket.dir = (self.controls.mouse_pos - self.player.pos).normalize();
-            self.add_enemy(rocket);
+            let rocket = create_projectile(
+                "Rocket".to_string(),
+                ProjectileOwner::Player,
+                self.player.pos,
+                (self.controls.mouse_pos - self.player.pos).normalize(),
+                res,
+            )
+            .expect("player projectiles are checked when loading resources");
+            self.add_projectile(rocket);
             self.player.q.last_use = Some(time);
+            self.emit(GameEvent::AbilityUsed {
+                user: Actor::Player,
+                name: self.player.q.name.to_string(),
+            });
         }
 
         let enemy_idx = self.get_enemy_index_at_pos(self.controls.mouse_pos);
-        if (self.controls.is_right_mouse_click || self.controls.is_right_mouse_down)
-            && enemy_idx.is_some()
-        {
+        let is_right_mouse =
+            self.controls.is_right_mouse_click || self.controls.is_right_mouse_down;
+        if let Some(enemy_idx) = enemy_idx.filter(|_| is_right_mouse) {
             if !self.player.auto.is_on_cooldown(time) {
-                let mut shot = create_enemy("Shot".to_string(), self.player.pos, res);
-                shot.dir = (self.enemies[enemy_idx.unwrap()].pos - self.player.pos).normalize();
-                self.add_enemy(shot);
+                let shot = create_projectile(
+                    "Shot".to_string(),
+                    ProjectileOwner::Player,
+                    self.player.pos,
+                    (self.enemies[enemy_idx].pos - self.player.pos).normalize(),
+                    res,
+                )
+                .expect("player projectiles are checked when loading resources");
+                self.add_projectile(shot);
                 self.player.auto.last_use = Some(time);
+                self.emit(GameEvent::AbilityUsed {
+                    user: Actor::Player,
+                    name: self.player.auto.name.to_string(),
+                });
             }
             self.player.target_pos = None
         } else {
-            self.update_player()
+            self.update_player(dt, res)
         }
+        self.take_stairs();
 
         self.update_level();
+        self.pick_up_keys();
         self.update_camera();
+        self.update_outcome();
+
+        self.clock.advance(dt);
+        self.stats.time_spent = self.clock.now();
+    }
+
+    fn update_outcome(&mut self) {
+        if self.outcome.is_some() {
+            return;
+        }
+        if self.floor + 1 == self.floors && count_nexus(&self.enemies) == 0 {
+            self.outcome = Some(LevelOutcome::Won);
+            self.emit(GameEvent::LevelWon);
+        } else if self.player.hp == 0 {
+            self.outcome = Some(LevelOutcome::Lost);
+            self.emit(GameEvent::LevelLost);
+        }
     }
 
     pub fn get_enemy_index_at_pos(&self, pos: Vec2) -> Option<usize> {
-        for i in 0..self.enemies.len() {
+        for i in self.enemy_grid.query(pos, Vec2::ZERO) {
             let rect = Rect {
                 x: self.enemies[i].pos.x - self.enemies[i].dim.x / 2.,
                 y: self.enemies[i].pos.y - self.enemies[i].dim.y / 2.,
@@ -102,10 +302,15 @@ impl<'a> Game<'a> {
     }
 
     pub fn offset(&self) -> Vec2 {
+        self.offset_in(vec2(screen_width(), screen_height()))
+    }
+
+    // offset of the game world when drawn on a screen of the given size
+    pub fn offset_in(&self, screen: Vec2) -> Vec2 {
         let mut off_x = 0.;
         let mut off_y = 0.;
-        let cam_w = screen_width();
-        let cam_h = screen_height();
+        let cam_w = screen.x;
+        let cam_h = screen.y;
         if cam_w > self.world.dim.x {
             off_x = (cam_w - self.world.dim.x) / 2.;
         }
@@ -118,3 +323,136 @@ impl<'a> Game<'a> {
         vec2(game_off_x, game_off_y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::resources::{
+        tests::{level_from_rows, resources},
+        LevelEnemyDefinition, Point,
+    };
+
+    // a single room of 8x5 floor tiles surrounded by walls
+    const ROWS: [&str; 7] = [
+        "aaaaaaaaaa",
+        "acccccccca",
+        "acccccccca",
+        "acccccccca",
+        "acccccccca",
+        "acccccccca",
+        "aaaaaaaaaa",
+    ];
+
+    pub(super) fn game(enemies: Vec<LevelEnemyDefinition>, res: &Resources) -> Game {
+        let lvl = level_from_rows(&ROWS, vec![]);
+        let lvl_def = LevelDefinition {
+            tiles: ROWS.iter().map(|row| row.to_string()).collect(),
+            player: Point { x: 48., y: 48. },
+            enemies,
+            keys: vec![],
+            secret_doors: vec![],
+        };
+        Game::new(lvl, lvl_def, 1, vec2(800., 600.), res).unwrap()
+    }
+
+    pub(super) fn nexus_at(x: f32, y: f32) -> LevelEnemyDefinition {
+        LevelEnemyDefinition {
+            name: "Nexus".to_string(),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn step_advances_the_clock_by_one_tick() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(240., 144.)], &res);
+        for _ in 0..60 {
+            game.step(Controls::default(), &res);
+        }
+        assert!((game.clock.now() - 1.).abs() < 1e-4);
+        assert_eq!(game.stats.time_spent, game.clock.now());
+    }
+
+    #[test]
+    fn player_walks_to_the_clicked_position() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        let controls = Controls {
+            mouse_pos: vec2(176., 48.),
+            is_right_mouse_click: true,
+            ..Controls::default()
+        };
+        game.step(controls, &res);
+        for _ in 0..60 {
+            game.step(controls.without_clicks(), &res);
+        }
+        assert!(game.player.pos.distance(vec2(176., 48.)) < 5.);
+        assert!(game.player.target_pos.is_none());
+    }
+
+    #[test]
+    fn player_stops_at_walls() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        let controls = Controls {
+            mouse_pos: vec2(-100., 48.),
+            is_right_mouse_down: true,
+            ..Controls::default()
+        };
+        for _ in 0..60 {
+            game.step(controls, &res);
+        }
+        assert!(!game.lvl.is_solid_at(game.player.pos));
+        assert!(game.player.pos.x >= TILE_SIZE);
+    }
+
+    #[test]
+    fn q_launches_a_rocket_once_per_cooldown() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        let controls = Controls {
+            mouse_pos: vec2(200., 48.),
+            is_q: true,
+            ..Controls::default()
+        };
+        game.step(controls, &res);
+        game.step(controls, &res);
+        let rockets = game
+            .drain_events()
+            .into_iter()
+            .filter(|event| matches!(event, GameEvent::ProjectileSpawned { name, .. } if name == "Rocket"))
+            .count();
+        assert_eq!(rockets, 1);
+        assert!(game.player.q.is_on_cooldown(game.clock.now()));
+    }
+
+    #[test]
+    fn killing_the_last_nexus_wins_the_level() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(176., 48.)], &res);
+        let controls = Controls {
+            mouse_pos: vec2(176., 48.),
+            is_right_mouse_down: true,
+            ..Controls::default()
+        };
+        // the nexus has 1000 hp, shots do 10x damage to the last one
+        for _ in 0..3000 {
+            game.step(controls, &res);
+            if game.outcome.is_some() {
+                break;
+            }
+        }
+        assert!(game.outcome == Some(LevelOutcome::Won));
+        assert!(game.drain_events().contains(&GameEvent::LevelWon));
+    }
+
+    #[test]
+    fn player_without_hp_loses_the_level() {
+        let res = resources();
+        let mut game = game(vec![nexus_at(272., 176.)], &res);
+        game.player.hp = 0;
+        game.step(Controls::default(), &res);
+        assert!(game.outcome == Some(LevelOutcome::Lost));
+    }
+}
@@ -1,12 +1,16 @@
 use macroquad::prelude::*;
 
 use super::{
-    enemy::{Enemy, EnemyStrategy},
+    enemy::Enemy,
     entity::{collides, Entity},
-    gfx::StaticSprite,
+    gfx::{Frame, StaticSprite},
     inventory::Inventory,
+    level::TILE_SIZE,
+    resources::{Resources, PLAYER_TEXTURE},
+    spatial::SpatialGrid,
 };
 
+#[derive(Clone)]
 pub struct Ability {
     pub name: String,
     pub cooldown: f64,
@@ -15,29 +19,35 @@ pub struct Ability {
 
 impl Ability {
     pub fn is_on_cooldown(&self, time: f64) -> bool {
-        if self.last_use.is_some() {
-            let cooldown_end = self.last_use.unwrap() + self.cooldown;
+        if let Some(last_use) = self.last_use {
+            let cooldown_end = last_use + self.cooldown;
             return cooldown_end > time;
         }
         false
     }
 
     pub fn cooldown_left(&self, time: f64) -> f64 {
-        if self.last_use.is_some() {
-            let cooldown_end = self.last_use.unwrap() + self.cooldown;
+        if let Some(last_use) = self.last_use {
+            let cooldown_end = last_use + self.cooldown;
             return cooldown_end - time;
         }
         0.0
     }
 }
 
-pub struct Player<'a> {
+const HYOTTOKO_OFFSET: f32 = 64.;
+
+#[derive(Clone)]
+pub struct Player {
     pub pos: Vec2,
     pub target_pos: Option<Vec2>,
     pub dim: Vec2,
     pub light_radius: usize,
-    pub sprite: StaticSprite<'a>,
+    pub sprite: StaticSprite,
     pub inventory: Inventory,
+    // locks the player has the key for
+    pub keys: Vec<usize>,
+    // pixels per second
     pub speed: f32,
     pub hp: usize,
     pub hp_max: usize,
@@ -46,7 +56,7 @@ pub struct Player<'a> {
     pub q: Ability,
 }
 
-impl<'a> Entity for Player<'a> {
+impl Entity for Player {
     fn dim(&self) -> Vec2 {
         self.dim
     }
@@ -56,25 +66,25 @@ impl<'a> Entity for Player<'a> {
     }
 }
 
-fn collides_any(player: &Player, enemies: &Vec<Enemy>) -> bool {
-    for enemy in enemies {
-        if matches!(enemy.strategy, EnemyStrategy::Projectile) {
-            continue;
-        }
-        if collides(player, enemy) {
+fn collides_any(player: &Player, enemies: &[Enemy], grid: &SpatialGrid) -> bool {
+    for i in grid.query(player.pos, player.dim) {
+        if collides(player, &enemies[i]) {
             return true;
         }
     }
     false
 }
 
-impl<'a> super::Game<'a> {
-    pub(super) fn update_player(&mut self) {
+impl super::Game {
+    pub(super) fn update_player(&mut self, dt: f32, res: &Resources) {
         let player = &mut self.player;
         let enemies = &self.enemies;
+        let grid = &self.enemy_grid;
 
         let controls = &self.controls;
         let lvl = &self.lvl;
+        // solid position the player tried to walk into, might be a door
+        let mut blocked_at = None;
 
         if controls.is_right_mouse_click || controls.is_right_mouse_down {
             player.target_pos = Some(self.controls.mouse_pos);
@@ -87,22 +97,81 @@ impl<'a> super::Game<'a> {
                 player.target_pos = None
             } else {
                 let dir = (target_pos - player.pos).normalize();
-                player.pos.x += dir.x * player.speed;
+                player.pos.x += dir.x * player.speed * dt;
 
                 // keep player on non-solid blocks
-                if lvl.is_solid_at(player.pos) || collides_any(player, enemies) {
+                if lvl.is_solid_at(player.pos) {
+                    blocked_at = Some(player.pos);
+                }
+                if lvl.is_solid_at(player.pos) || collides_any(player, enemies, grid) {
                     // put player back where they were
-                    player.pos.x -= dir.x * player.speed;
+                    player.pos.x -= dir.x * player.speed * dt;
                 }
 
-                player.pos.y += dir.y * player.speed;
+                player.pos.y += dir.y * player.speed * dt;
 
                 // keep player on non-solid blocks
-                if lvl.is_solid_at(player.pos) || collides_any(player, enemies) {
+                if lvl.is_solid_at(player.pos) {
+                    blocked_at = Some(player.pos);
+                }
+                if lvl.is_solid_at(player.pos) || collides_any(player, enemies, grid) {
                     // put player back where they were
-                    player.pos.y -= dir.y * player.speed;
+                    player.pos.y -= dir.y * player.speed * dt;
                 }
             }
         }
+
+        if let Some(pos) = blocked_at {
+            self.open_door_at(pos, res);
+        }
+    }
+}
+
+pub fn create_player(pos: Vec2, res: &Resources) -> Player {
+    Player {
+        pos,
+        dim: vec2(TILE_SIZE, TILE_SIZE),
+        target_pos: None,
+        speed: 180.,
+        light_radius: 4,
+        keys: vec![],
+        hp: 100,
+        hp_max: 100,
+        sprite: StaticSprite {
+            frame: Frame {
+                texture: res.texture_id(PLAYER_TEXTURE).unwrap(),
+                source_rect: Rect {
+                    x: HYOTTOKO_OFFSET,
+                    y: 0.,
+                    w: TILE_SIZE,
+                    h: TILE_SIZE,
+                },
+                dest_size: Vec2 {
+                    x: TILE_SIZE,
+                    y: TILE_SIZE,
+                },
+            },
+        },
+        inventory: Inventory {
+            amulet: None,
+            head: None,
+            body: None,
+            arm1: None,
+            arm2: None,
+            ring1: None,
+            ring2: None,
+            belt: None,
+            foot: None,
+        },
+        q: Ability {
+            cooldown: 4.,
+            last_use: None,
+            name: "Q".to_string(),
+        },
+        auto: Ability {
+            cooldown: 0.7,
+            last_use: None,
+            name: "AUTO".to_string(),
+        },
     }
 }
@@ -0,0 +1,209 @@
+use macroquad::prelude::*;
+
+use super::{
+    entity::{collides, is_out_of_lvl_bounds, Entity},
+    error::ResourceError,
+    events::{Actor, GameEvent},
+    gfx::{AnimatedSprite, Frame},
+    resources::Resources,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProjectileOwner {
+    Player,
+    // id of the enemy that fired the projectile
+    Enemy(usize),
+}
+
+#[derive(Clone)]
+pub struct Projectile {
+    pub name: String,
+    pub owner: ProjectileOwner,
+    pub pos: Vec2,
+    pub dim: Vec2,
+    pub dir: Vec2,
+    // pixels per second
+    pub speed: f32,
+    pub sprite: AnimatedSprite,
+    pub fog_of_war: bool,
+    pub damage: usize,
+    // number of targets the projectile passes through after the first hit
+    pub pierce: usize,
+    // pixels, the projectile expires after travelling this far
+    pub max_range: f32,
+    // seconds, the projectile expires after being alive this long
+    pub lifetime: f32,
+    pub traveled: f32,
+    pub age: f32,
+    // ids of the enemies that were already hit, so that a piercing projectile
+    // does not hit the same enemy on every tick
+    pub hit: Vec<usize>,
+    pub is_used_up: bool,
+}
+
+impl Entity for Projectile {
+    fn dim(&self) -> Vec2 {
+        self.dim
+    }
+
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+}
+
+impl Projectile {
+    fn is_expired(&self) -> bool {
+        self.is_used_up || self.traveled >= self.max_range || self.age >= self.lifetime
+    }
+
+    // registers a hit, returns false if the target was already hit before
+    fn register_hit(&mut self, target_id: usize) -> bool {
+        if self.hit.contains(&target_id) {
+            return false;
+        }
+        self.hit.push(target_id);
+        if self.hit.len() > self.pierce {
+            self.is_used_up = true;
+        }
+        true
+    }
+}
+
+impl super::Game {
+    pub fn add_projectile(&mut self, projectile: Projectile) {
+        self.emit(GameEvent::ProjectileSpawned {
+            owner: projectile.owner,
+            name: projectile.name.to_string(),
+        });
+        self.projectiles.push(projectile);
+    }
+
+    pub(super) fn update_projectiles(&mut self, dt: f32) {
+        for projectile in &mut self.projectiles {
+            let step = projectile.speed * dt;
+            projectile.pos += projectile.dir * step;
+            projectile.traveled += step;
+            projectile.age += dt;
+            projectile.sprite.update(dt);
+        }
+
+        self.resolve_projectile_hits();
+
+        let lvl = &self.lvl;
+        self.projectiles.retain(|projectile| {
+            !projectile.is_expired() && !is_out_of_lvl_bounds(projectile, lvl)
+        });
+    }
+
+    // applies the damage of all projectiles that hit something in this tick
+    fn resolve_projectile_hits(&mut self) {
+        let enemies = &mut self.enemies;
+        let grid = &self.enemy_grid;
+        let player = &mut self.player;
+        let events = &mut self.events;
+        let enemy_count = enemies.len();
+
+        for projectile in &mut self.projectiles {
+            match projectile.owner {
+                ProjectileOwner::Player => {
+                    for i in grid.query(projectile.pos, projectile.dim) {
+                        let enemy = &mut enemies[i];
+                        if projectile.is_used_up {
+                            break;
+                        }
+                        if enemy.hp == 0 || !collides(projectile, enemy) {
+                            continue;
+                        }
+                        if !projectile.register_hit(enemy.id) {
+                            continue;
+                        }
+                        let mut damage = projectile.damage;
+                        // make nexus take 10* damage if its the last enemy
+                        if enemy.name == "Nexus" && enemy_count == 1 {
+                            damage *= 10;
+                        }
+                        enemy.hp -= std::cmp::min(enemy.hp, damage);
+                        events.push(GameEvent::DamageDealt {
+                            source: projectile.owner.into(),
+                            target: Actor::Enemy(enemy.id),
+                            amount: damage,
+                        });
+                        if enemy.hp == 0 {
+                            events.push(GameEvent::EnemyKilled {
+                                id: enemy.id,
+                                name: enemy.name.to_string(),
+                            });
+                        }
+                    }
+                }
+                ProjectileOwner::Enemy(_) => {
+                    if collides(projectile, player) {
+                        player.hp -= std::cmp::min(player.hp, projectile.damage);
+                        events.push(GameEvent::DamageReceived {
+                            source: projectile.owner.into(),
+                            target: Actor::Player,
+                            amount: projectile.damage,
+                        });
+                        projectile.is_used_up = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn create_projectile(
+    name: String,
+    owner: ProjectileOwner,
+    pos: Vec2,
+    dir: Vec2,
+    res: &Resources,
+) -> Result<Projectile, ResourceError> {
+    let def = res
+        .projectile_definitions
+        .get(&name)
+        .ok_or_else(|| ResourceError::UnknownName {
+            kind: "projectile",
+            name: name.to_string(),
+            valid: res.projectile_names(),
+        })?;
+    let tex = res.texture_id(&def.sprite.texture).unwrap();
+
+    let mut frames = vec![];
+    for frame_def in &def.sprite.frames {
+        frames.push(Frame {
+            texture: tex,
+            dest_size: vec2(def.dim.x, def.dim.y),
+            source_rect: Rect {
+                x: frame_def.x,
+                y: frame_def.y,
+                w: def.dim.x,
+                h: def.dim.y,
+            },
+        })
+    }
+
+    Ok(Projectile {
+        name: def.name.to_string(),
+        owner,
+        pos,
+        dim: vec2(def.dim.x, def.dim.y),
+        dir,
+        speed: def.speed,
+        sprite: AnimatedSprite {
+            frames,
+            fps: def.sprite.fps,
+            frame_index: 0,
+            time: 0.,
+        },
+        fog_of_war: def.fog_of_war,
+        damage: def.damage,
+        pierce: def.pierce,
+        max_range: def.max_range,
+        lifetime: def.lifetime,
+        traveled: 0.,
+        age: 0.,
+        hit: vec![],
+        is_used_up: false,
+    })
+}
@@ -4,10 +4,32 @@ use macroquad::{
     file::load_string,
     texture::{load_texture, Texture2D},
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    door::{KEY_TEXTURES, LOCKED_DOORS},
+    enemy::EnemyStrategy,
+    error::ResourceError,
+    gfx::TextureId,
+    level::TILE_SIZE,
+};
+
+pub const PLAYER_TEXTURE: &str = "sprites_for_para.png";
+
+pub const ENEMIES_FILE: &str = "resources/enemies.json";
+pub const PROJECTILES_FILE: &str = "resources/projectiles.json";
+pub const TILES_FILE: &str = "resources/tiles.json";
+pub const BIOMES_FILE: &str = "resources/biomes.json";
+// hand made levels the wfc generator learns from
+pub const SAMPLE_LEVEL_FILES: [&str; 2] = ["resources/level1.json", "resources/level2.json"];
+// list of the prefab files in resources/prefabs/
+pub const PREFABS_INDEX_FILE: &str = "resources/prefabs/index.json";
+
+// projectiles fired by the player abilities
+pub const PLAYER_PROJECTILES: [&str; 2] = ["Rocket", "Shot"];
 
 // like Vec2, only needed for de/serialization
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Point {
     pub x: f32,
     pub y: f32,
@@ -24,6 +46,7 @@ pub struct EnemySpriteDefinition {
 pub struct EnemyDefinition {
     pub name: String,
     pub strategy: String,
+    // pixels per second
     pub speed: f32,
     pub speed_solid: f32,
     pub sprite: EnemySpriteDefinition,
@@ -31,85 +54,808 @@ pub struct EnemyDefinition {
     pub fog_of_war: bool,
     pub damage: usize,
     pub hp_max: usize,
+    // difficulty points the enemy costs when populating generated levels,
+    // enemies without cost are never placed there
+    #[serde(default)]
+    pub cost: usize,
 }
 
 #[derive(Serialize, Deserialize)]
+pub struct ProjectileDefinition {
+    pub name: String,
+    // pixels per second
+    pub speed: f32,
+    pub sprite: EnemySpriteDefinition,
+    pub dim: Point,
+    pub fog_of_war: bool,
+    pub damage: usize,
+    pub pierce: usize,
+    // pixels
+    pub max_range: f32,
+    // seconds
+    pub lifetime: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TileDefinition {
     pub name: String,
     pub ch: char,
     pub texture: String,
     pub solid: bool,
+    // wall variant: replaces the wall variant_of in generated levels where
+    // the walkable neighbours are exactly one of the masks, a mask being a
+    // list of neighbours (n, ne, e, se, s, sw, w, nw)
+    #[serde(default)]
+    pub variant_of: Option<char>,
+    #[serde(default)]
+    pub masks: Vec<Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize)]
+// neighbours as named in the masks of tile definitions, with their offsets
+pub const NEIGHBOURS: [(&str, i32, i32); 8] = [
+    ("n", 0, -1),
+    ("ne", 1, -1),
+    ("e", 1, 0),
+    ("se", 1, 1),
+    ("s", 0, 1),
+    ("sw", -1, 1),
+    ("w", -1, 0),
+    ("nw", -1, -1),
+];
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct LevelEnemyDefinition {
     pub name: String,
     pub x: f32,
     pub y: f32,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct LevelKeyDefinition {
+    // index into door::LOCKED_DOORS
+    pub lock: usize,
+    pub x: f32,
+    pub y: f32,
+}
+
+// position in tiles
+#[derive(Serialize, Deserialize)]
+pub struct TilePosition {
+    pub x: usize,
+    pub y: usize,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct LevelDefinition {
     pub tiles: Vec<String>,
     pub player: Point,
     pub enemies: Vec<LevelEnemyDefinition>,
+    #[serde(default)]
+    pub keys: Vec<LevelKeyDefinition>,
+    // walls that open like doors
+    #[serde(default)]
+    pub secret_doors: Vec<TilePosition>,
+}
+
+// hand made room that level generators can place instead of a plain room
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefabDefinition {
+    pub name: String,
+    // same tile chars as levels, the outer ring should be walls
+    pub tiles: Vec<String>,
+    // positions in pixels, relative to the top left corner of the prefab
+    pub enemies: Vec<LevelEnemyDefinition>,
+    // spots where a key can be placed when the key goes to the prefab, in
+    // pixels like the enemies
+    #[serde(default)]
+    pub keys: Vec<Point>,
+    // placed exactly as authored, never rotated or mirrored
+    #[serde(default)]
+    pub fixed: bool,
+}
+
+// look of a part of a generated level and what lives there
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BiomeDefinition {
+    pub name: String,
+    // solid
+    pub wall: char,
+    pub corridor_wall: char,
+    // not solid
+    pub floor: char,
+    pub corridor: char,
+    pub decorations: Vec<char>,
+    // names of the enemies that are placed in rooms of the biome
+    pub enemies: Vec<String>,
 }
 
 pub struct Resources {
     pub enemy_definitions: HashMap<String, EnemyDefinition>,
+    pub projectile_definitions: HashMap<String, ProjectileDefinition>,
     pub tile_defintions: HashMap<char, TileDefinition>,
-    pub textures: HashMap<String, Texture2D>,
+    pub textures: Vec<Texture2D>,
+    pub texture_ids: HashMap<String, TextureId>,
 }
 
-pub async fn load_enemy_definitions() -> HashMap<String, EnemyDefinition> {
-    let json_string = load_string("resources/enemies.json").await.unwrap();
-    let defs: Vec<EnemyDefinition> = serde_json::from_str(&json_string).unwrap();
-    defs.into_iter()
+async fn read_file(file: &str) -> Result<String, ResourceError> {
+    load_string(file).await.map_err(|err| ResourceError::Io {
+        file: file.to_string(),
+        message: err.to_string(),
+    })
+}
+
+// parses a json string, errors contain the json path of the value that could
+// not be parsed
+pub fn parse_json<T: DeserializeOwned>(file: &str, json_string: &str) -> Result<T, ResourceError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(json_string);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let line = err.inner().line();
+        let column = err.inner().column();
+        // the position is part of the error struct already
+        let message = err.inner().to_string();
+        let message = message.trim_end_matches(&format!(" at line {} column {}", line, column));
+        ResourceError::Parse {
+            file: file.to_string(),
+            path: err.path().to_string(),
+            line,
+            column,
+            message: message.to_string(),
+        }
+    })
+}
+
+pub async fn load_enemy_definitions() -> Result<HashMap<String, EnemyDefinition>, ResourceError> {
+    parse_enemy_definitions(ENEMIES_FILE, &read_file(ENEMIES_FILE).await?)
+}
+
+pub fn parse_enemy_definitions(
+    file: &str,
+    json_string: &str,
+) -> Result<HashMap<String, EnemyDefinition>, ResourceError> {
+    let defs: Vec<EnemyDefinition> = parse_json(file, json_string)?;
+    for (i, def) in defs.iter().enumerate() {
+        if EnemyStrategy::from_name(&def.strategy).is_none() {
+            return Err(ResourceError::UnknownReference {
+                file: file.to_string(),
+                path: format!("[{}].strategy", i),
+                value: def.strategy.to_string(),
+                valid: EnemyStrategy::NAMES.iter().map(|s| s.to_string()).collect(),
+            });
+        }
+    }
+    Ok(defs
+        .into_iter()
+        .map(|def| (def.name.to_string(), def))
+        .collect::<HashMap<String, EnemyDefinition>>())
+}
+
+pub async fn load_projectile_definitions(
+) -> Result<HashMap<String, ProjectileDefinition>, ResourceError> {
+    parse_projectile_definitions(PROJECTILES_FILE, &read_file(PROJECTILES_FILE).await?)
+}
+
+pub fn parse_projectile_definitions(
+    file: &str,
+    json_string: &str,
+) -> Result<HashMap<String, ProjectileDefinition>, ResourceError> {
+    let defs: Vec<ProjectileDefinition> = parse_json(file, json_string)?;
+    let defs = defs
+        .into_iter()
         .map(|def| (def.name.to_string(), def))
-        .collect::<HashMap<String, EnemyDefinition>>()
+        .collect::<HashMap<String, ProjectileDefinition>>();
+    // the player abilities rely on these being defined
+    for name in PLAYER_PROJECTILES {
+        if !defs.contains_key(name) {
+            return Err(ResourceError::MissingDefinition {
+                file: file.to_string(),
+                name: name.to_string(),
+            });
+        }
+    }
+    Ok(defs)
+}
+
+pub async fn load_tile_definitions() -> Result<HashMap<char, TileDefinition>, ResourceError> {
+    parse_tile_definitions(TILES_FILE, &read_file(TILES_FILE).await?)
 }
 
-pub async fn load_tile_definitions() -> HashMap<char, TileDefinition> {
-    let json_string = load_string("resources/tiles.json").await.unwrap();
-    let defs: Vec<TileDefinition> = serde_json::from_str(&json_string).unwrap();
-    defs.into_iter()
+pub fn parse_tile_definitions(
+    file: &str,
+    json_string: &str,
+) -> Result<HashMap<char, TileDefinition>, ResourceError> {
+    let defs: Vec<TileDefinition> = parse_json(file, json_string)?;
+    let chars: Vec<String> = defs.iter().map(|def| def.ch.to_string()).collect();
+    // (wall, sorted neighbours) -> variant that has the mask, a second one
+    // would make the choice depend on the order of the HashMap
+    let mut variants: HashMap<(char, Vec<&str>), usize> = HashMap::new();
+    for (i, def) in defs.iter().enumerate() {
+        if let Some(wall) = def.variant_of {
+            if !defs.iter().any(|other| other.ch == wall) {
+                return Err(ResourceError::UnknownReference {
+                    file: file.to_string(),
+                    path: format!("[{}].variant_of", i),
+                    value: wall.to_string(),
+                    valid: chars,
+                });
+            }
+        }
+        for (j, mask) in def.masks.iter().enumerate() {
+            for (k, name) in mask.iter().enumerate() {
+                if !NEIGHBOURS.iter().any(|(neighbour, _, _)| neighbour == name) {
+                    return Err(ResourceError::UnknownReference {
+                        file: file.to_string(),
+                        path: format!("[{}].masks[{}][{}]", i, j, k),
+                        value: name.to_string(),
+                        valid: NEIGHBOURS
+                            .iter()
+                            .map(|(name, _, _)| name.to_string())
+                            .collect(),
+                    });
+                }
+            }
+            if let Some(wall) = def.variant_of {
+                let mut names: Vec<&str> = mask.iter().map(String::as_str).collect();
+                names.sort();
+                names.dedup();
+                if let Some(other) = variants.insert((wall, names), i) {
+                    return Err(ResourceError::Invalid {
+                        file: file.to_string(),
+                        path: format!("[{}].masks[{}]", i, j),
+                        message: format!(
+                            "[{}] is already the variant of {} with this mask",
+                            other, wall
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    Ok(defs
+        .into_iter()
         .map(|def| (def.ch, def))
-        .collect::<HashMap<char, TileDefinition>>()
+        .collect::<HashMap<char, TileDefinition>>())
+}
+
+pub async fn load_prefab_definitions(
+    res: &Resources,
+) -> Result<Vec<PrefabDefinition>, ResourceError> {
+    let index = read_file(PREFABS_INDEX_FILE).await?;
+    // files can only be read asynchronously here, so all of them are read
+    // before parsing
+    let mut files = HashMap::new();
+    for file in parse_json::<Vec<String>>(PREFABS_INDEX_FILE, &index)? {
+        let path = prefab_path(&file);
+        let json_string = read_file(&path).await?;
+        files.insert(path, json_string);
+    }
+    parse_prefab_definitions(
+        &index,
+        |path| Ok(files.remove(path).unwrap_or_default()),
+        res,
+    )
+}
+
+// the prefabs listed in the index (the contents of PREFABS_INDEX_FILE), read
+// is called with the path of every prefab file and returns its contents
+pub fn parse_prefab_definitions(
+    index: &str,
+    mut read: impl FnMut(&str) -> Result<String, ResourceError>,
+    res: &Resources,
+) -> Result<Vec<PrefabDefinition>, ResourceError> {
+    let files: Vec<String> = parse_json(PREFABS_INDEX_FILE, index)?;
+    let mut defs = vec![];
+    for file in files {
+        let path = prefab_path(&file);
+        defs.push(parse_prefab_definition(&path, &read(&path)?, res)?);
+    }
+    Ok(defs)
+}
+
+// path of a file listed in the prefab index
+fn prefab_path(file: &str) -> String {
+    format!("resources/prefabs/{}", file)
+}
+
+pub fn parse_prefab_definition(
+    file: &str,
+    json_string: &str,
+    res: &Resources,
+) -> Result<PrefabDefinition, ResourceError> {
+    let def: PrefabDefinition = parse_json(file, json_string)?;
+    let width = def
+        .tiles
+        .first()
+        .map(|row| row.chars().count())
+        .unwrap_or(0);
+    if width == 0 {
+        return Err(ResourceError::Invalid {
+            file: file.to_string(),
+            path: "tiles".to_string(),
+            message: "a prefab needs at least one tile".to_string(),
+        });
+    }
+    for (y, row) in def.tiles.iter().enumerate() {
+        if row.chars().count() != width {
+            return Err(ResourceError::Invalid {
+                file: file.to_string(),
+                path: format!("tiles[{}]", y),
+                message: format!("all rows must be {} tiles long", width),
+            });
+        }
+        if let Some(ch) = row
+            .chars()
+            .find(|ch| *ch != ' ' && !res.tile_defintions.contains_key(ch))
+        {
+            return Err(ResourceError::UnknownReference {
+                file: file.to_string(),
+                path: format!("tiles[{}]", y),
+                value: ch.to_string(),
+                valid: res.tile_chars(),
+            });
+        }
+    }
+    for (i, enemy) in def.enemies.iter().enumerate() {
+        if !res.enemy_definitions.contains_key(&enemy.name) {
+            return Err(ResourceError::UnknownReference {
+                file: file.to_string(),
+                path: format!("enemies[{}].name", i),
+                value: enemy.name.to_string(),
+                valid: res.enemy_names(),
+            });
+        }
+    }
+    for (i, key) in def.keys.iter().enumerate() {
+        let (x, y) = (key.x / TILE_SIZE, key.y / TILE_SIZE);
+        let walkable = x >= 0.
+            && y >= 0.
+            && def
+                .tiles
+                .get(y as usize)
+                .and_then(|row| row.chars().nth(x as usize))
+                .and_then(|ch| res.tile_defintions.get(&ch))
+                .is_some_and(|tile| !tile.solid);
+        if !walkable {
+            return Err(ResourceError::Invalid {
+                file: file.to_string(),
+                path: format!("keys[{}]", i),
+                message: "keys must be on a tile that is not solid".to_string(),
+            });
+        }
+    }
+    Ok(def)
+}
+
+pub async fn load_biome_definitions(
+    res: &Resources,
+) -> Result<Vec<BiomeDefinition>, ResourceError> {
+    parse_biome_definitions(BIOMES_FILE, &read_file(BIOMES_FILE).await?, res)
+}
+
+pub fn parse_biome_definitions(
+    file: &str,
+    json_string: &str,
+    res: &Resources,
+) -> Result<Vec<BiomeDefinition>, ResourceError> {
+    let defs: Vec<BiomeDefinition> = parse_json(file, json_string)?;
+    for (i, def) in defs.iter().enumerate() {
+        let mut tiles = vec![
+            ("wall", def.wall, true),
+            ("corridor_wall", def.corridor_wall, true),
+            ("floor", def.floor, false),
+            ("corridor", def.corridor, false),
+        ];
+        for ch in &def.decorations {
+            tiles.push(("decorations", *ch, false));
+        }
+        for (field, ch, solid) in tiles {
+            let path = format!("[{}].{}", i, field);
+            let Some(tile) = res.tile_defintions.get(&ch) else {
+                return Err(ResourceError::UnknownReference {
+                    file: file.to_string(),
+                    path,
+                    value: ch.to_string(),
+                    valid: res.tile_chars(),
+                });
+            };
+            if tile.solid != solid {
+                return Err(ResourceError::Invalid {
+                    file: file.to_string(),
+                    path,
+                    message: format!(
+                        "{} must be a {} tile",
+                        ch,
+                        if solid { "solid" } else { "walkable" }
+                    ),
+                });
+            }
+        }
+        for (j, name) in def.enemies.iter().enumerate() {
+            if !res.enemy_definitions.contains_key(name) {
+                return Err(ResourceError::UnknownReference {
+                    file: file.to_string(),
+                    path: format!("[{}].enemies[{}]", i, j),
+                    value: name.to_string(),
+                    valid: res.enemy_names(),
+                });
+            }
+        }
+    }
+    Ok(defs)
 }
 
-pub async fn load_level_definition(path: &str) -> LevelDefinition {
-    let json_string = load_string(path).await.unwrap();
-    serde_json::from_str(&json_string).unwrap()
+pub async fn load_level_definition(
+    path: &str,
+    res: &Resources,
+) -> Result<LevelDefinition, ResourceError> {
+    let def: LevelDefinition = parse_json(path, &read_file(path).await?)?;
+    res.validate_level_definition(path, &def)?;
+    Ok(def)
 }
 
 impl Resources {
-    pub async fn load_textures(mut self) -> Self {
-        for (_, def) in &self.enemy_definitions {
-            if self.textures.contains_key(&def.sprite.texture) {
-                continue;
+    // assigns a texture id to every texture referenced by the definitions,
+    // the textures themselves are loaded by load_textures, so that
+    // Resources can also be used without a window
+    pub fn new(
+        enemy_definitions: HashMap<String, EnemyDefinition>,
+        projectile_definitions: HashMap<String, ProjectileDefinition>,
+        tile_defintions: HashMap<char, TileDefinition>,
+    ) -> Self {
+        let mut names: Vec<String> = vec![PLAYER_TEXTURE.to_string()];
+        for def in enemy_definitions.values() {
+            names.push(def.sprite.texture.to_string());
+        }
+        for def in projectile_definitions.values() {
+            names.push(def.sprite.texture.to_string());
+        }
+        for def in tile_defintions.values() {
+            names.push(def.texture.to_string());
+        }
+        for texture in KEY_TEXTURES {
+            names.push(texture.to_string());
+        }
+        names.sort();
+        names.dedup();
+        let texture_ids = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, TextureId(i)))
+            .collect();
+
+        Resources {
+            enemy_definitions,
+            projectile_definitions,
+            tile_defintions,
+            textures: vec![],
+            texture_ids,
+        }
+    }
+
+    pub async fn load_textures(mut self) -> Result<Self, ResourceError> {
+        let mut names: Vec<(&String, &TextureId)> = self.texture_ids.iter().collect();
+        names.sort_by_key(|(_, id)| id.0);
+        let mut textures = vec![];
+        for (name, _) in names {
+            let path = format!("resources/textures/{}", name);
+            let val = load_texture(&path).await.map_err(|err| ResourceError::Io {
+                file: path.to_string(),
+                message: err.to_string(),
+            })?;
+            textures.push(val);
+        }
+        self.textures = textures;
+        Ok(self)
+    }
+
+    // checks that all enemies referenced by the level are defined and keys
+    // belong to existing locks
+    pub fn validate_level_definition(
+        &self,
+        file: &str,
+        def: &LevelDefinition,
+    ) -> Result<(), ResourceError> {
+        for (i, enemy) in def.enemies.iter().enumerate() {
+            if !self.enemy_definitions.contains_key(&enemy.name) {
+                return Err(ResourceError::UnknownReference {
+                    file: file.to_string(),
+                    path: format!("enemies[{}].name", i),
+                    value: enemy.name.to_string(),
+                    valid: self.enemy_names(),
+                });
             }
-            let path = format!("resources/textures/{}", def.sprite.texture);
-            let key = def.sprite.texture.to_string();
-            let val = load_texture(&path).await.unwrap();
-            self.textures.insert(key, val);
-        }
-        for (_, def) in &self.tile_defintions {
-            if self.textures.contains_key(&def.texture) {
-                continue;
+        }
+        for (i, key) in def.keys.iter().enumerate() {
+            if key.lock >= LOCKED_DOORS.len() {
+                return Err(ResourceError::UnknownReference {
+                    file: file.to_string(),
+                    path: format!("keys[{}].lock", i),
+                    value: key.lock.to_string(),
+                    valid: (0..LOCKED_DOORS.len()).map(|i| i.to_string()).collect(),
+                });
             }
-            let path = format!("resources/textures/{}", def.texture);
-            let key = def.texture.to_string();
-            let val = load_texture(&path).await.unwrap();
-            self.textures.insert(key, val);
         }
-        self
+        Ok(())
+    }
+
+    // sorted names of all defined enemies
+    pub fn enemy_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.enemy_definitions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // sorted chars of all defined tiles
+    pub fn tile_chars(&self) -> Vec<String> {
+        let mut chars: Vec<char> = self.tile_defintions.keys().copied().collect();
+        chars.sort();
+        chars.into_iter().map(|ch| ch.to_string()).collect()
+    }
+
+    // sorted names of all defined projectiles
+    pub fn projectile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.projectile_definitions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn texture_id(&self, name: &str) -> Option<TextureId> {
+        self.texture_ids.get(name).copied()
+    }
+
+    pub fn texture(&self, id: TextureId) -> &Texture2D {
+        &self.textures[id.0]
     }
 
     pub fn tile_texture(&self, ch: char) -> Option<&Texture2D> {
         let def = self.tile_defintions.get(&ch);
         if let Some(def) = def {
-            return Some(&self.textures[&def.texture]);
+            return self.texture_id(&def.texture).map(|id| self.texture(id));
         }
         None
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::game::level::{Level, Room};
+
+    fn read(file: &str) -> String {
+        std::fs::read_to_string(file).unwrap()
+    }
+
+    // definitions from resources/, without textures
+    pub(crate) fn resources() -> Resources {
+        Resources::new(
+            parse_enemy_definitions(ENEMIES_FILE, &read(ENEMIES_FILE)).unwrap(),
+            parse_projectile_definitions(PROJECTILES_FILE, &read(PROJECTILES_FILE)).unwrap(),
+            parse_tile_definitions(TILES_FILE, &read(TILES_FILE)).unwrap(),
+        )
+    }
+
+    // level made of the given rows of tiles of the game, with the given rooms
+    pub(crate) fn level_from_rows(rows: &[&str], rooms: Vec<Room>) -> Level {
+        let res = resources();
+        let mut level = Level::load_from_string(&(rows.join("\n") + "\n"), &res.tile_defintions);
+        level.rooms = rooms;
+        level
+    }
+
+    const ENEMY: &str = r#"{
+        "name": "Blob", "strategy": "verticalPatrol", "speed": 90,
+        "speed_solid": 0, "fog_of_war": true, "damage": 3, "hp_max": 50,
+        "sprite": { "texture": "blob.png", "frames": [{ "x": 0, "y": 0 }], "fps": 5 },
+        "dim": { "x": 32, "y": 32 }
+    }"#;
+
+    #[test]
+    fn resource_files_are_valid() {
+        let res = resources();
+        assert!(res.enemy_definitions.contains_key("Nexus"));
+        for file in SAMPLE_LEVEL_FILES {
+            let def: LevelDefinition = parse_json(file, &read(file)).unwrap();
+            res.validate_level_definition(file, &def).unwrap();
+        }
+        let biomes = parse_biome_definitions(BIOMES_FILE, &read(BIOMES_FILE), &res).unwrap();
+        assert!(!biomes.is_empty());
+        let files: Vec<String> = parse_json(PREFABS_INDEX_FILE, &read(PREFABS_INDEX_FILE)).unwrap();
+        for file in files {
+            let path = prefab_path(&file);
+            parse_prefab_definition(&path, &read(&path), &res).unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_errors_name_file_path_and_position() {
+        let json = format!("[{}]", ENEMY.replace("\"fps\": 5", "\"fps\": \"fast\""));
+        let err = parse_enemy_definitions("enemies.json", &json)
+            .err()
+            .unwrap();
+        let ResourceError::Parse {
+            file, path, line, ..
+        } = &err
+        else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert_eq!(file, "enemies.json");
+        assert_eq!(path, "[0].sprite.fps");
+        assert_eq!(*line, 4);
+        assert!(err.to_string().starts_with("enemies.json:4:"));
+    }
+
+    #[test]
+    fn unknown_strategies_list_the_valid_ones() {
+        let json = format!("[{}]", ENEMY.replace("verticalPatrol", "teleport"));
+        let err = parse_enemy_definitions("enemies.json", &json)
+            .err()
+            .unwrap();
+        let ResourceError::UnknownReference {
+            path, value, valid, ..
+        } = &err
+        else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert_eq!(path, "[0].strategy");
+        assert_eq!(value, "teleport");
+        assert_eq!(valid.len(), EnemyStrategy::NAMES.len());
+        assert!(err.to_string().contains("followPlayer"));
+    }
+
+    #[test]
+    fn player_projectiles_must_be_defined() {
+        let json = read(PROJECTILES_FILE).replace("\"Rocket\"", "\"Missile\"");
+        let err = parse_projectile_definitions("projectiles.json", &json)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, ResourceError::MissingDefinition { ref name, .. } if name == "Rocket")
+        );
+    }
+
+    #[test]
+    fn unknown_neighbours_in_masks_are_rejected() {
+        let json = r#"[
+            { "name": "Wall", "ch": "a", "texture": "a.png", "solid": true },
+            { "name": "Face", "ch": "n", "texture": "n.png", "solid": true,
+              "variant_of": "a", "masks": [["s"], ["down"]] }
+        ]"#;
+        let err = parse_tile_definitions("tiles.json", json).err().unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::UnknownReference { ref path, ref value, .. }
+                if path == "[1].masks[1][0]" && value == "down"
+        ));
+    }
+
+    #[test]
+    fn variants_with_the_same_mask_are_rejected() {
+        let json = r#"[
+            { "name": "Wall", "ch": "a", "texture": "a.png", "solid": true },
+            { "name": "Corner", "ch": "n", "texture": "n.png", "solid": true,
+              "variant_of": "a", "masks": [["s", "e"]] },
+            { "name": "Other Corner", "ch": "o", "texture": "o.png", "solid": true,
+              "variant_of": "a", "masks": [["n"], ["e", "s"]] }
+        ]"#;
+        let err = parse_tile_definitions("tiles.json", json).err().unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::Invalid { ref path, .. } if path == "[2].masks[1]"
+        ));
+    }
+
+    #[test]
+    fn levels_with_unknown_enemies_and_locks_are_rejected() {
+        let res = resources();
+        let mut def: LevelDefinition = parse_json(
+            "level.json",
+            r#"{ "tiles": ["aaa"], "player": { "x": 0, "y": 0 },
+                 "enemies": [{ "name": "Nexus", "x": 0, "y": 0 }],
+                 "keys": [{ "lock": 0, "x": 0, "y": 0 }] }"#,
+        )
+        .unwrap();
+        res.validate_level_definition("level.json", &def).unwrap();
+
+        def.keys[0].lock = LOCKED_DOORS.len();
+        let err = res
+            .validate_level_definition("level.json", &def)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::UnknownReference { ref path, .. } if path == "keys[0].lock"
+        ));
+
+        def.enemies[0].name = "Nexxus".to_string();
+        let err = res
+            .validate_level_definition("level.json", &def)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "level.json: at `enemies[0].name`: unknown value \"Nexxus\", valid values are: {}",
+                res.enemy_names().join(", ")
+            )
+        );
+    }
+
+    #[test]
+    fn prefab_rows_must_have_the_same_length() {
+        let res = resources();
+        let json = r#"{ "name": "broken", "tiles": ["aaa", "aca", "aa"], "enemies": [] }"#;
+        let err = parse_prefab_definition("broken.json", json, &res)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::Invalid { ref path, .. } if path == "tiles[2]"
+        ));
+    }
+
+    #[test]
+    fn prefab_keys_must_be_on_walkable_tiles() {
+        let res = resources();
+        let json = r#"{ "name": "room", "tiles": ["aaa", "aca", "aaa"], "enemies": [],
+            "keys": [{ "x": 48, "y": 48 }, { "x": 16, "y": 48 }] }"#;
+        let err = parse_prefab_definition("room.json", json, &res)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::Invalid { ref path, .. } if path == "keys[1]"
+        ));
+    }
+
+    #[test]
+    fn prefabs_are_read_from_the_files_of_the_index() {
+        let res = resources();
+        let mut read = vec![];
+        let defs = parse_prefab_definitions(
+            r#"["a.json", "b.json"]"#,
+            |path| {
+                read.push(path.to_string());
+                Ok(
+                    r#"{ "name": "room", "tiles": ["aaa", "aca", "aaa"], "enemies": [] }"#
+                        .to_string(),
+                )
+            },
+            &res,
+        )
+        .unwrap();
+        assert_eq!(defs.len(), 2);
+        assert_eq!(
+            read,
+            ["resources/prefabs/a.json", "resources/prefabs/b.json"]
+        );
+
+        let err = parse_prefab_definitions(
+            r#"["missing.json"]"#,
+            |path| {
+                Err(ResourceError::Io {
+                    file: path.to_string(),
+                    message: "not found".to_string(),
+                })
+            },
+            &res,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(err, ResourceError::Io { ref file, .. } if file.ends_with("missing.json"))
+        );
+    }
+
+    #[test]
+    fn biome_floors_must_be_walkable() {
+        let res = resources();
+        let json = r#"[{ "name": "Broken", "wall": "a", "corridor_wall": "b",
+            "floor": "a", "corridor": "c", "decorations": [], "enemies": [] }]"#;
+        let err = parse_biome_definitions("biomes.json", json, &res)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ResourceError::Invalid { ref path, .. } if path == "[0].floor"
+        ));
+    }
+}
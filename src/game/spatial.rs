@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use macroquad::math::Vec2;
+
+use super::{entity::Entity, level::TILE_SIZE};
+
+// spatial hash with cells of TILE_SIZE
+// stores indexes of entities (eg. into Game::enemies) in every cell that
+// their bounding box overlaps, so that collision checks only need to look at
+// entities in the cells around a position instead of all of them
+#[derive(Clone, Default)]
+pub struct SpatialGrid {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+fn cell_range(pos: Vec2, dim: Vec2) -> ((i32, i32), (i32, i32)) {
+    let min = (
+        ((pos.x - dim.x / 2.) / TILE_SIZE).floor() as i32,
+        ((pos.y - dim.y / 2.) / TILE_SIZE).floor() as i32,
+    );
+    let max = (
+        ((pos.x + dim.x / 2.) / TILE_SIZE).floor() as i32,
+        ((pos.y + dim.y / 2.) / TILE_SIZE).floor() as i32,
+    );
+    (min, max)
+}
+
+impl SpatialGrid {
+    pub fn rebuild(&mut self, entities: &[impl Entity]) {
+        self.cells.clear();
+        for (i, entity) in entities.iter().enumerate() {
+            self.insert(i, entity.pos(), entity.dim());
+        }
+    }
+
+    pub fn insert(&mut self, idx: usize, pos: Vec2, dim: Vec2) {
+        let (min, max) = cell_range(pos, dim);
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                self.cells.entry((x, y)).or_default().push(idx);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, idx: usize, pos: Vec2, dim: Vec2) {
+        let (min, max) = cell_range(pos, dim);
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                if let Some(cell) = self.cells.get_mut(&(x, y)) {
+                    cell.retain(|&i| i != idx);
+                    if cell.is_empty() {
+                        self.cells.remove(&(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    // moves an entity that was inserted with old_pos to new_pos
+    pub fn update(&mut self, idx: usize, old_pos: Vec2, new_pos: Vec2, dim: Vec2) {
+        if cell_range(old_pos, dim) == cell_range(new_pos, dim) {
+            return;
+        }
+        self.remove(idx, old_pos, dim);
+        self.insert(idx, new_pos, dim);
+    }
+
+    // indexes of all entities in the cells overlapped by the given box,
+    // sorted ascending and without duplicates
+    pub fn query(&self, pos: Vec2, dim: Vec2) -> Vec<usize> {
+        let mut result = vec![];
+        let (min, max) = cell_range(pos, dim);
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    result.extend_from_slice(cell);
+                }
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+}
@@ -0,0 +1,5 @@
+// the game simulation, level generation and resource loading
+// the windowed front end lives in main.rs, other binaries (tools,
+// experiments) can link against this library to reuse the same code
+pub mod args;
+pub mod game;
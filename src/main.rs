@@ -1,21 +1,28 @@
-mod game;
-
-use std::collections::HashMap;
-
-use game::camera::GameCamera;
-use game::controls::Controls;
-use game::enemy::{count_nexus, create_enemy, Enemy, EnemyStrategy};
-use game::gfx::{Frame, StaticSprite};
-use game::inventory::Inventory;
-use game::level::{FogLevel, Level, World, TILE_SIZE};
-use game::level_generator::generate_level;
-use game::player::{Ability, Player};
-use game::resources::{
-    load_enemy_definitions, load_level_definition, load_tile_definitions, LevelDefinition,
-    LevelEnemyDefinition, Point, Resources,
-};
-use game::{Game, GameState, GameStats};
+use std::error::Error;
+
 use macroquad::prelude::*;
+use rust_thing2::args::arg_value;
+use rust_thing2::game::door::{Key, KEY_TEXTURES};
+use rust_thing2::game::enemy::Enemy;
+use rust_thing2::game::error::ResourceError;
+use rust_thing2::game::events::{EventListener, GameEvent};
+use rust_thing2::game::gfx::Frame;
+use rust_thing2::game::level::{FogLevel, Level, TILE_SIZE};
+use rust_thing2::game::level_generator::dungeon::{
+    floor_seed, generate_floor, DungeonParams, DEFAULT_BUDGET_PER_FLOOR,
+};
+use rust_thing2::game::level_generator::endpoints::EndpointParams;
+use rust_thing2::game::level_generator::{
+    generator_by_name, FailedSeeds, GeneratorParams, LevelGenerator, RandomRoomGenerator,
+    GENERATOR_NAMES,
+};
+use rust_thing2::game::player::Ability;
+use rust_thing2::game::projectile::Projectile;
+use rust_thing2::game::resources::{
+    load_biome_definitions, load_enemy_definitions, load_level_definition, load_prefab_definitions,
+    load_projectile_definitions, load_tile_definitions, Resources, SAMPLE_LEVEL_FILES,
+};
+use rust_thing2::game::{Game, GameState, LevelOutcome};
 
 fn window_conf() -> Conf {
     Conf {
@@ -28,21 +35,17 @@ fn window_conf() -> Conf {
     }
 }
 
-const HYOTTOKO_OFFSET: f32 = 64.;
-
-fn draw_enemy(enemy: &Enemy, game_off: Vec2, is_in_fog: bool) {
+fn draw_enemy(enemy: &Enemy, game_off: Vec2, is_in_fog: bool, res: &Resources) {
     if !enemy.fog_of_war || !is_in_fog {
         draw_frame(
             &enemy.sprite.frames[enemy.sprite.frame_index],
             enemy.pos.x - (enemy.dim.x / 2.) + game_off.x,
             enemy.pos.y - (enemy.dim.y / 2.) + game_off.y,
+            res,
         );
     }
 
-    if !is_in_fog
-        && !matches!(enemy.strategy, EnemyStrategy::Projectile)
-        && enemy.hp != enemy.hp_max
-    {
+    if !is_in_fog && enemy.hp != enemy.hp_max {
         // max hp red
         draw_rectangle(
             enemy.pos.x - (enemy.dim.x / 2.) + game_off.x,
@@ -62,9 +65,34 @@ fn draw_enemy(enemy: &Enemy, game_off: Vec2, is_in_fog: bool) {
     }
 }
 
-fn draw_frame(frame: &Frame, x: f32, y: f32) {
+fn draw_projectile(projectile: &Projectile, game_off: Vec2, is_in_fog: bool, res: &Resources) {
+    if !projectile.fog_of_war || !is_in_fog {
+        draw_frame(
+            &projectile.sprite.frames[projectile.sprite.frame_index],
+            projectile.pos.x - (projectile.dim.x / 2.) + game_off.x,
+            projectile.pos.y - (projectile.dim.y / 2.) + game_off.y,
+            res,
+        );
+    }
+}
+
+fn draw_key(key: &Key, game_off: Vec2, is_in_fog: bool, res: &Resources) {
+    if is_in_fog {
+        return;
+    }
+    if let Some(id) = res.texture_id(KEY_TEXTURES[key.lock]) {
+        draw_texture(
+            res.texture(id),
+            key.pos.x - TILE_SIZE / 2. + game_off.x,
+            key.pos.y - TILE_SIZE / 2. + game_off.y,
+            WHITE,
+        );
+    }
+}
+
+fn draw_frame(frame: &Frame, x: f32, y: f32, res: &Resources) {
     draw_texture_ex(
-        frame.texture,
+        res.texture(frame.texture),
         x,
         y,
         WHITE,
@@ -76,8 +104,8 @@ fn draw_frame(frame: &Frame, x: f32, y: f32) {
     );
 }
 
-fn draw_ability(x: f32, y: f32, ability: &Ability) {
-    let cooldown_left = ability.cooldown_left(get_time());
+fn draw_ability(x: f32, y: f32, ability: &Ability, time: f64) {
+    let cooldown_left = ability.cooldown_left(time);
     if cooldown_left > 0.0 {
         draw_rectangle(x, y, 48., 48., GRAY);
         draw_rectangle(x + 2., y + 2., 44., 44., LIGHTGRAY);
@@ -127,10 +155,22 @@ fn draw_game(
         }
     }
 
+    // draw keys
+    for key in &game.lvl.keys {
+        let is_in_fog = game.lvl.is_fog_of_war_at(key.pos);
+        draw_key(key, game_off, is_in_fog, res)
+    }
+
     // draw enemies
     for enemy in &game.enemies {
         let is_in_fog = game.lvl.is_fog_of_war_at(enemy.pos);
-        draw_enemy(enemy, game_off, is_in_fog)
+        draw_enemy(enemy, game_off, is_in_fog, res)
+    }
+
+    // draw projectiles
+    for projectile in &game.projectiles {
+        let is_in_fog = game.lvl.is_fog_of_war_at(projectile.pos);
+        draw_projectile(projectile, game_off, is_in_fog, res)
     }
 
     // draw player
@@ -138,6 +178,7 @@ fn draw_game(
         &game.player.sprite.frame,
         game.player.pos.x - (game.player.dim.x / 2.) + game_off.x,
         game.player.pos.y - (game.player.dim.y / 2.) + game_off.y,
+        res,
     );
 
     // draw fog
@@ -170,7 +211,7 @@ fn draw_game(
     }
 }
 
-fn draw_hud(game: &Game) {
+fn draw_hud(game: &Game, res: &Resources) {
     let hud_height = 64.0;
     draw_rectangle(
         0.0,
@@ -202,11 +243,84 @@ fn draw_hud(game: &Game) {
         BLACK,
     );
 
+    // keys, below the HP bar
+    for (i, lock) in game.player.keys.iter().enumerate() {
+        if let Some(id) = res.texture_id(KEY_TEXTURES[*lock]) {
+            draw_texture_ex(
+                res.texture(id),
+                x + i as f32 * 24.,
+                y + bar_height + 4.,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(24., 24.)),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
     // abilities
     let mut x = 128. + border;
-    draw_ability(x, y, &game.player.auto);
+    draw_ability(x, y, &game.player.auto, game.clock.now());
     x += 48.0 + border;
-    draw_ability(x, y, &game.player.q);
+    draw_ability(x, y, &game.player.q, game.clock.now());
+
+    // seed
+    x += 48.0 + border;
+    draw_text(
+        format!("SEED: {}", game.seed).as_str(),
+        x,
+        y + 11.0,
+        20.0,
+        BLACK,
+    );
+
+    // floor
+    if game.floors > 1 {
+        draw_text(
+            format!("FLOOR: {}/{}", game.floor + 1, game.floors).as_str(),
+            x,
+            y + 31.0,
+            20.0,
+            BLACK,
+        );
+    }
+
+    // time scale
+    if game.clock.scale() != 1. {
+        draw_text(
+            format!("SPEED: x{:.2}", game.clock.scale()).as_str(),
+            x,
+            y + 51.0,
+            20.0,
+            BLACK,
+        );
+    }
+}
+
+// seed given on the command line via `--seed <number>`
+fn seed_from_args() -> Option<u64> {
+    match arg_value("--seed").map(|arg| arg.parse()) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => {
+            println!("invalid value for --seed, using a random seed");
+            None
+        }
+        None => None,
+    }
+}
+
+// level generator given on the command line via `--generator <name>`
+fn generator_from_args() -> Box<dyn LevelGenerator> {
+    let name = arg_value("--generator").unwrap_or("random".to_string());
+    generator_by_name(&name).unwrap_or_else(|| {
+        println!(
+            "unknown generator {}, valid generators are: {}, using random",
+            name,
+            GENERATOR_NAMES.join(", ")
+        );
+        Box::new(RandomRoomGenerator)
+    })
 }
 
 fn draw_debug(game: &Game, game_off: Vec2) {
@@ -228,9 +342,9 @@ fn draw_debug(game: &Game, game_off: Vec2) {
         DARKGRAY,
     );
 
-    // debug enemy* count (incl. projectiles)
+    // debug enemy and projectile count
     draw_text(
-        format!("{:?}", game.enemies.len()).as_str(),
+        format!("{:?} {:?}", game.enemies.len(), game.projectiles.len()).as_str(),
         20.0,
         60.0,
         30.0,
@@ -238,8 +352,7 @@ fn draw_debug(game: &Game, game_off: Vec2) {
     );
 
     // debug player target position
-    if game.player.target_pos.is_some() {
-        let target_pos = game.player.target_pos.unwrap();
+    if let Some(target_pos) = game.player.target_pos {
         draw_line(
             game.player.pos.x + game_off.x,
             game.player.pos.y + game_off.y,
@@ -311,159 +424,189 @@ fn draw_debug(game: &Game, game_off: Vec2) {
     }
 }
 
-async fn init_level<'a>(name: &'a str, res: &'a Resources) -> Game<'a> {
-    let lvl_def: LevelDefinition;
-    let lvl: Level;
-    if name.is_empty() {
-        lvl = generate_level(40, 150, 20);
-        let start = lvl.rooms[0].center();
-        let end = lvl.rooms[lvl.rooms.len() - 1].center();
-        lvl_def = LevelDefinition {
-            tiles: vec![],
-            player: Point {
-                x: start.x * TILE_SIZE,
-                y: start.y * TILE_SIZE,
-            },
-            enemies: vec![LevelEnemyDefinition {
-                name: "Nexus".to_string(),
-                x: end.x * TILE_SIZE,
-                y: end.y * TILE_SIZE,
-            }],
-        };
-    } else {
-        lvl_def = load_level_definition(name).await;
-        lvl = Level::load_from_string(&(lvl_def.tiles.join("\n") + "\n"))
-    };
-
-    let world = World {
-        dim: vec2(
-            (lvl.width as f32) * TILE_SIZE,
-            (lvl.height as f32) * TILE_SIZE,
-        ),
-    };
+// generated runs go this many floors deep, with locked rooms on each
+const DUNGEON: DungeonParams = DungeonParams {
+    floors: 3,
+    locks: 2,
+    budget_per_floor: DEFAULT_BUDGET_PER_FLOOR,
+    endpoints: EndpointParams {
+        min_distance: 0,
+        goal_in_dead_end: false,
+    },
+};
 
-    let controls = Controls {
-        mouse_pos: vec2(0., 0.),
-        is_left_mouse_click: false,
-        is_right_mouse_click: false,
-        is_right_mouse_down: false,
-        is_q: false,
-    };
+async fn init_level(
+    name: &str,
+    seed: u64,
+    generator: &dyn LevelGenerator,
+    params: &GeneratorParams,
+    res: &Resources,
+) -> Result<Game, Box<dyn Error>> {
+    if name.is_empty() {
+        // the game keeps the seed of the run, not the one the floor ended up
+        // being generated from after retries, all floors derive from it
+        let (lvl, lvl_def, _, failed) =
+            generate_floor(generator, params, &DUNGEON, 0, floor_seed(seed, 0), res)?;
+        print_failed_seeds(&failed);
+        let mut game = Game::new(
+            lvl,
+            lvl_def,
+            seed,
+            vec2(screen_width(), screen_height() - 64.),
+            res,
+        )?;
+        game.floors = DUNGEON.floors;
+        return Ok(game);
+    }
 
-    let player = Player {
-        pos: vec2(lvl_def.player.x, lvl_def.player.y),
-        dim: vec2(TILE_SIZE, TILE_SIZE),
-        target_pos: None,
-        speed: 3.,
-        light_radius: 4,
-        hp: 100,
-        hp_max: 100,
-        sprite: StaticSprite {
-            frame: Frame {
-                texture: res.textures.get("sprites_for_para.png").unwrap(),
-                source_rect: Rect {
-                    x: HYOTTOKO_OFFSET,
-                    y: 0.,
-                    w: TILE_SIZE,
-                    h: TILE_SIZE,
-                },
-                dest_size: Vec2 {
-                    x: TILE_SIZE,
-                    y: TILE_SIZE,
-                },
-            },
-        },
-        inventory: Inventory {
-            amulet: None,
-            head: None,
-            body: None,
-            arm1: None,
-            arm2: None,
-            ring1: None,
-            ring2: None,
-            belt: None,
-            foot: None,
-        },
-        q: Ability {
-            cooldown: 4.,
-            last_use: None,
-            name: "Q".to_string(),
-        },
-        auto: Ability {
-            cooldown: 0.7,
-            last_use: None,
-            name: "AUTO".to_string(),
-        },
-    };
+    let lvl_def = load_level_definition(name, res).await?;
+    let lvl = Level::load_from_string(&(lvl_def.tiles.join("\n") + "\n"), &res.tile_defintions);
+    Ok(Game::new(
+        lvl,
+        lvl_def,
+        seed,
+        vec2(screen_width(), screen_height() - 64.),
+        res,
+    )?)
+}
 
-    let mut cam_w = screen_width();
-    let mut cam_h = screen_height() - 64.;
-    if cam_w > world.dim.x {
-        cam_w = world.dim.x
+fn print_failed_seeds(failed: &FailedSeeds) {
+    for (seed, err) in failed {
+        println!("generating with seed {} failed: {}", seed, err);
     }
-    if cam_h > world.dim.y {
-        cam_h = world.dim.y
+}
+
+// continues the game on the given floor, generating it when it is visited
+// the first time
+fn change_floor(
+    game: &mut Game,
+    floor: usize,
+    generator: &dyn LevelGenerator,
+    params: &GeneratorParams,
+    res: &Resources,
+) -> Result<(), Box<dyn Error>> {
+    if game.enter_floor(floor) {
+        return Ok(());
     }
-    let camera = GameCamera {
-        dim: vec2(cam_w, cam_h),
-        pos: vec2(player.pos.x - cam_w / 2., player.pos.y - cam_h / 2.),
-    };
+    let seed = floor_seed(game.seed, floor);
+    let (lvl, lvl_def, _, failed) = generate_floor(generator, params, &DUNGEON, floor, seed, res)?;
+    print_failed_seeds(&failed);
+    game.enter_new_floor(floor, lvl, lvl_def, res)?;
+    Ok(())
+}
 
-    let mut game = Game {
-        lvl,
-        player,
-        enemies: vec![],
-        world,
-        controls,
-        camera,
-        stats: GameStats {
-            damage_dealt: 0,
-            damage_received: 0,
-            enemies_killed: 0,
-            time_spent: 0.,
-        },
-    };
+async fn load_fog_texture(path: &str) -> Result<Texture2D, ResourceError> {
+    load_texture(path).await.map_err(|err| ResourceError::Io {
+        file: path.to_string(),
+        message: err.to_string(),
+    })
+}
 
-    for enemy in lvl_def.enemies {
-        game.add_enemy(create_enemy(enemy.name, vec2(enemy.x, enemy.y), res));
-    }
+async fn load_resources() -> Result<(Resources, Texture2D, Texture2D), ResourceError> {
+    let res = Resources::new(
+        load_enemy_definitions().await?,
+        load_projectile_definitions().await?,
+        load_tile_definitions().await?,
+    )
+    .load_textures()
+    .await?;
+
+    let fog = load_fog_texture("resources/fog.png").await?;
+    let fog_half_transparent = load_fog_texture("resources/fog_half_transparent.png").await?;
+    Ok((res, fog, fog_half_transparent))
+}
+
+// shows the error until the window is closed
+async fn show_error(err: &dyn Error) -> ! {
+    println!("{}", err);
 
-    game
+    let font_size = 30.;
+    loop {
+        clear_background(BLACK);
+
+        // wrap the message at the screen border
+        let max_width = screen_width() - 40.;
+        let mut y = 60.;
+        draw_text("ERROR", 20., y, 60., RED);
+        y += 40.;
+        let mut line = String::new();
+        for word in err.to_string().split(' ') {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if !line.is_empty()
+                && measure_text(&candidate, None, font_size as u16, 1.).width > max_width
+            {
+                draw_text(&line, 20., y, font_size, WHITE);
+                y += font_size;
+                line = word.to_string();
+            } else {
+                line = candidate;
+            }
+        }
+        draw_text(&line, 20., y, font_size, WHITE);
+
+        next_frame().await
+    }
 }
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let mut res = Resources {
-        enemy_definitions: load_enemy_definitions().await,
-        tile_defintions: load_tile_definitions().await,
-        textures: HashMap::new(),
+    let (res, fog, fog_half_transparent) = match load_resources().await {
+        Ok(loaded) => loaded,
+        Err(err) => show_error(&err).await,
     };
 
-    res = res.load_textures().await;
-
-    let fog: Texture2D = load_texture("resources/fog.png").await.unwrap();
-    let fog_half_transparent: Texture2D = load_texture("resources/fog_half_transparent.png")
-        .await
-        .unwrap();
+    let mut generator_params = GeneratorParams::new(40, 150, 20);
+    generator_params.tiles = res.tile_defintions.clone();
+    generator_params.prefabs = match load_prefab_definitions(&res).await {
+        Ok(prefabs) => prefabs,
+        Err(err) => show_error(&err).await,
+    };
+    generator_params.biomes = match load_biome_definitions(&res).await {
+        Ok(biomes) => biomes,
+        Err(err) => show_error(&err).await,
+    };
+    for file in SAMPLE_LEVEL_FILES {
+        match load_level_definition(file, &res).await {
+            Ok(def) => generator_params.samples.push(def.tiles),
+            Err(err) => show_error(&err).await,
+        }
+    }
 
     let mut state = GameState::MainMenu;
 
+    let seed_arg = seed_from_args();
+    let generator = generator_from_args();
+    let mut seed = 0;
+
     let levels = []; // ["resources/level1.json", "resources/level2.json"];
     let mut level_idx = 0;
     let mut option_game = None;
 
     let mut mouse_down = false;
-    let mut last_time = None;
 
     loop {
-        let time = get_time();
         if option_game.is_none() {
             let level_name = if level_idx < levels.len() {
                 levels[level_idx]
             } else {
                 ""
             };
-            option_game = Some(init_level(level_name, &res).await);
+            match init_level(
+                level_name,
+                seed,
+                generator.as_ref(),
+                &generator_params,
+                &res,
+            )
+            .await
+            {
+                Ok(game) => option_game = Some(game),
+                Err(err) => show_error(err.as_ref()).await,
+            }
         }
 
         let game = option_game.as_mut().unwrap();
@@ -476,25 +619,58 @@ async fn main() {
 
         match state {
             GameState::InGame => {
-                if last_time.is_some() {
-                    game.stats.time_spent += time - last_time.unwrap();
-                }
-                last_time = Some(time);
-
                 // hud
-                draw_hud(game);
+                draw_hud(game, &res);
 
                 // debug stuff
                 draw_debug(game, game_off);
 
+                if is_key_pressed(KeyCode::P) || is_key_pressed(KeyCode::Escape) {
+                    game.clock.pause();
+                    state = GameState::Paused;
+                }
+
+                // time scale
+                if is_key_pressed(KeyCode::Minus) {
+                    game.clock.set_scale(game.clock.scale() / 2.);
+                } else if is_key_pressed(KeyCode::Equal) {
+                    game.clock.set_scale(game.clock.scale() * 2.);
+                } else if is_key_pressed(KeyCode::Key0) {
+                    game.clock.set_scale(1.);
+                }
+
                 game.update(&res);
-                if count_nexus(&game.enemies) == 0 || game.player.hp == 0 {
-                    state = GameState::PostGame;
-                    mouse_down = false;
+                for event in game.drain_events() {
+                    game.stats.on_event(&event);
+                    if let GameEvent::StairsTaken { to, .. } = event {
+                        if let Err(err) =
+                            change_floor(game, to, generator.as_ref(), &generator_params, &res)
+                        {
+                            show_error(err.as_ref()).await;
+                        }
+                    }
+                    if matches!(event, GameEvent::LevelWon | GameEvent::LevelLost) {
+                        state = GameState::PostGame;
+                        mouse_down = false;
+                    }
+                }
+            }
+            GameState::Paused => {
+                draw_hud(game, &res);
+
+                let text = "PAUSED";
+                let size = measure_text(text, None, 100, 1.0);
+                let x = (screen_width() - size.width) / 2.;
+                let y = (screen_height() - size.height) / 2. + size.offset_y;
+                draw_text(text, x, y, 100.0, WHITE);
+
+                if is_key_pressed(KeyCode::P) || is_key_pressed(KeyCode::Escape) {
+                    game.clock.resume();
+                    state = GameState::InGame;
                 }
             }
             GameState::PostGame => {
-                let victory = count_nexus(&game.enemies) == 0;
+                let victory = game.outcome == Some(LevelOutcome::Won);
                 let text;
                 let color;
                 if victory {
@@ -524,6 +700,10 @@ async fn main() {
 
                 let text = format!("DAMAGE RECEIVED: {:?}", game.stats.damage_received);
                 draw_text(text.as_str(), x, y, 30.0, WHITE);
+                y += 30.;
+
+                let text = format!("SEED: {}", game.seed);
+                draw_text(text.as_str(), x, y, 30.0, WHITE);
                 y += 60.;
 
                 let text = match victory {
@@ -547,6 +727,8 @@ async fn main() {
                 }
             }
             GameState::MainMenu => {
+                // every run gets a new seed unless one was given at startup
+                seed = seed_arg.unwrap_or_else(|| fastrand::u64(..));
                 level_idx = 0;
                 option_game = None;
                 state = GameState::InGame;